@@ -1,9 +1,15 @@
-use std::{borrow::Cow, fmt::Display, path::Path, str::FromStr};
+use std::{borrow::Cow, fmt::Display, str::FromStr};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
 use is_terminal::IsTerminal;
 use itertools::Itertools;
+use regex::Regex;
+
+use crate::{
+    input::{InputKind, STDIN_PATH},
+    normalize::normalize,
+};
 
 /// The field on which to sort the output by.
 #[derive(Debug, Clone, Copy)]
@@ -133,8 +139,102 @@ impl Display for RelativeTo {
     }
 }
 
+/// An input given as a positional argument: `[<name>=][<kind>:]<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSpec {
+    /// The name of the run loaded from the input, if given.
+    pub name: Option<String>,
+    /// The kind of the input, if given rather than detected.
+    pub kind: Option<InputKind>,
+    /// The path of the input, or `-` for the standard input.
+    pub path: String,
+}
+
+impl FromStr for InputSpec {
+    type Err = anyhow::Error;
+
+    /// Parse an input spec.
+    ///
+    /// A prefix is only taken as a name if it has no path separator, and as a kind if it is a
+    /// known kind. Paths containing `=` or `:` can be prefixed with `./` to be taken as is.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = match s.split_once('=') {
+            Some((name, rest)) if !name.is_empty() && !name.contains(['/', '\\']) => {
+                (Some(name.to_string()), rest)
+            }
+            _ => (None, s),
+        };
+        let (kind, path) = match rest.split_once(':') {
+            Some((kind, path)) => match kind.parse::<InputKind>() {
+                Ok(kind) => (Some(kind), path),
+                Err(_) => (None, rest),
+            },
+            None => (None, rest),
+        };
+        if path.is_empty() {
+            bail!("Missing path in input {s}");
+        }
+        Ok(Self {
+            name,
+            kind,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl Display for InputSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Inputs to load as a single run, given with `--merge-into` or `--sample-group`:
+/// `<name>=<input>,<input>...`.
+///
+/// Each input is `[<kind>:]<path>`, as for positional inputs but without a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeInto {
+    /// The name of the merged run.
+    pub name: String,
+    /// The inputs that are merged or grouped into the run.
+    pub inputs: Vec<InputSpec>,
+}
+
+impl FromStr for MergeInto {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, inputs)) = s.split_once('=') else {
+            bail!("Invalid merge {s}. Expected `<name>=<input>,<input>...`");
+        };
+        if name.is_empty() || name.contains(['/', '\\']) {
+            bail!("Invalid run name `{name}` in merge {s}");
+        }
+        let inputs = inputs
+            .split(',')
+            .map(|input| {
+                let spec = input.parse::<InputSpec>()?;
+                if spec.name.is_some() {
+                    bail!("Input {input} of merge {s} cannot be named, the run is named {name}");
+                }
+                Ok(spec)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            name: name.to_string(),
+            inputs,
+        })
+    }
+}
+
+impl Display for MergeInto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
 /// What to show for each data column.
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Show {
     /// All columns.
     #[default]
@@ -145,6 +245,32 @@ pub enum Show {
     PercentageDiff,
     /// The difference in IR count with respect to [`RelativeTo`].
     IRCountDiff,
+    /// The inclusive IR count.
+    InclIRCount,
+    /// The percentage (or ratio) of increase/decrease of the inclusive IR count.
+    InclPercentageDiff,
+    /// The difference in inclusive IR count.
+    InclIRCountDiff,
+}
+
+impl Show {
+    /// Return whether the column shows inclusive costs.
+    pub fn is_inclusive(self) -> bool {
+        matches!(
+            self,
+            Self::InclIRCount | Self::InclPercentageDiff | Self::InclIRCountDiff
+        )
+    }
+
+    /// Return the inclusive counterpart of the column.
+    pub fn inclusive(self) -> Self {
+        match self {
+            Self::IRCount => Self::InclIRCount,
+            Self::PercentageDiff => Self::InclPercentageDiff,
+            Self::IRCountDiff => Self::InclIRCountDiff,
+            show => show,
+        }
+    }
 }
 
 impl FromStr for Show {
@@ -156,8 +282,12 @@ impl FromStr for Show {
             "ircount" => Ok(Self::IRCount),
             "percentagediff" => Ok(Self::PercentageDiff),
             "ircountdiff" => Ok(Self::IRCountDiff),
+            "inclircount" => Ok(Self::InclIRCount),
+            "inclpercentagediff" => Ok(Self::InclPercentageDiff),
+            "inclircountdiff" => Ok(Self::InclIRCountDiff),
             _ => bail!(
-                "Invalid show. Accepted values are: all, ircount, percentagediff, ircountdiff"
+                "Invalid show. Accepted values are: all, ircount, percentagediff, ircountdiff, \
+                 inclircount, inclpercentagediff, inclircountdiff"
             ),
         }
     }
@@ -169,6 +299,146 @@ impl Display for Show {
     }
 }
 
+/// How symbols are aggregated. The default is to aggregate by function name.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Symbols with the same function name are added together, whatever their file or object.
+    #[default]
+    Function,
+    /// Symbols from the same source file are added together.
+    File,
+    /// Symbols from the same object are added together.
+    Object,
+    /// Symbols are told apart by function name and source file.
+    FileFunction,
+    /// Symbols are told apart by function name and object.
+    ObjectFunction,
+}
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "function" => Ok(Self::Function),
+            "file" => Ok(Self::File),
+            "object" => Ok(Self::Object),
+            "file:function" => Ok(Self::FileFunction),
+            "object:function" => Ok(Self::ObjectFunction),
+            _ => bail!(
+                "Invalid group-by. Accepted values are: function, file, object, file:function, \
+                 object:function"
+            ),
+        }
+    }
+}
+
+impl Display for GroupBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// In which order the files of a directory or glob input are loaded.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub enum InputOrder {
+    /// By file name, with numbers in names compared by value (`run2` before `run10`).
+    #[default]
+    Natural,
+    /// By modification time, oldest first.
+    Mtime,
+    /// By the PID of the profiled process, as recorded in the file or in its name.
+    Pid,
+    /// By the value of a field of the header (`Timerange`, `Trigger`, `cmd`...), compared as
+    /// file names are.
+    Field(String),
+}
+
+impl FromStr for InputOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "natural" => Ok(Self::Natural),
+            "mtime" => Ok(Self::Mtime),
+            "pid" => Ok(Self::Pid),
+            s if s.starts_with("field:") => {
+                let key = &s["field:".len()..];
+                if key.is_empty() {
+                    bail!("input-order=field needs a key, e.g.: --input-order field:Timerange");
+                }
+                Ok(Self::Field(key.to_string()))
+            }
+            _ => bail!("Invalid input-order. Accepted values are: natural, mtime, pid, field:KEY"),
+        }
+    }
+}
+
+impl Display for InputOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// How the parts and threads of the same process are loaded.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parts {
+    /// One run per part or thread, labeled with it.
+    #[default]
+    Separate,
+    /// One run per process, with the costs of its parts and threads added up.
+    Sum,
+    /// One run per command line, with the costs of all its processes added up.
+    Command,
+}
+
+impl FromStr for Parts {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "separate" => Ok(Self::Separate),
+            "sum" => Ok(Self::Sum),
+            "command" => Ok(Self::Command),
+            _ => bail!("Invalid parts. Accepted values are: separate, sum, command"),
+        }
+    }
+}
+
+impl Display for Parts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// The layout of the JSON export.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// A single JSON document.
+    #[default]
+    Json,
+    /// One JSON object per line: the runs, then each symbol.
+    Ndjson,
+}
+
+impl FromStr for JsonFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            _ => bail!("Invalid JSON format. Accepted values are: json, ndjson"),
+        }
+    }
+}
+
+impl Display for JsonFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
 /// Whether to color the output.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
@@ -251,6 +521,193 @@ impl Display for StringReplacement {
     }
 }
 
+/// A regular expression replacement to perform on a symbol name.
+#[derive(Debug, Clone)]
+pub struct RegexReplacement {
+    /// The expression to look for.
+    regex: Regex,
+    /// What its matches will be replaced with, with `$1`, `${name}`, ... for capture groups.
+    replacement: String,
+}
+
+impl RegexReplacement {
+    /// Perform the replacement on all the matches in the given string.
+    pub fn perform<'a>(&self, s: Cow<'a, str>) -> Cow<'a, str> {
+        match self.regex.replace_all(&s, self.replacement.as_str()) {
+            Cow::Owned(replaced) => Cow::Owned(replaced),
+            Cow::Borrowed(_) => s,
+        }
+    }
+}
+
+impl FromStr for RegexReplacement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((regex, replacement)) = s.split_once("=>") else {
+            bail!("No '=>' in regex replacement");
+        };
+        Ok(Self {
+            regex: Regex::new(regex)?,
+            replacement: replacement.to_string(),
+        })
+    }
+}
+
+impl Display for RegexReplacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// A normalization pass on symbol names, so that they match across builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalize {
+    /// Demangle Rust (legacy and v0) and Itanium C++ mangled names.
+    Demangle,
+    /// Strip the hashes of Rust names (`::h1a2b3c4d5e6f7a8b`, `[1a2b3c4d]`).
+    Hashes,
+    /// Give all closures of a function the same name (`{closure#1}` becomes `{closure}`).
+    Closures,
+    /// Collapse generic parameters (`Vec<u8>` becomes `Vec<_>`).
+    Generics,
+}
+
+impl FromStr for Normalize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "demangle" => Ok(Self::Demangle),
+            "hashes" => Ok(Self::Hashes),
+            "closures" => Ok(Self::Closures),
+            "generics" => Ok(Self::Generics),
+            _ => bail!(
+                "Invalid normalization. Accepted values are: demangle, hashes, closures, generics"
+            ),
+        }
+    }
+}
+
+impl Display for Normalize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// The largest change of a cost allowed by `--check`, as a count or a percentage of the
+/// reference cost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// A change of at most this count (`1000`).
+    Count(i64),
+    /// A change of at most this percentage of the reference cost (`2.5%`).
+    Percentage(f64),
+}
+
+impl Budget {
+    /// Return whether a change of a cost, in the direction the budget is checked in (an increase
+    /// or a decrease), is beyond the budget.
+    ///
+    /// Any change of a cost that was 0 is beyond a percentage.
+    pub fn is_exceeded(self, change: i64, reference: i64) -> bool {
+        match self {
+            Self::Count(count) => change > count,
+            Self::Percentage(_) if reference == 0 => change > 0,
+            Self::Percentage(percentage) => {
+                change as f64 * 100.0 / reference.unsigned_abs() as f64 > percentage
+            }
+        }
+    }
+}
+
+impl FromStr for Budget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let budget = match s.strip_suffix('%') {
+            Some(percentage) => percentage.trim().parse::<f64>().ok().map(Self::Percentage),
+            None => s.trim().parse::<i64>().ok().map(Self::Count),
+        };
+        match budget {
+            Some(Self::Count(count)) if count >= 0 => Ok(Self::Count(count)),
+            Some(Self::Percentage(percentage)) if percentage >= 0.0 => {
+                Ok(Self::Percentage(percentage))
+            }
+            _ => bail!("Invalid budget {s}. Expected a count (`1000`) or a percentage (`2.5%`)"),
+        }
+    }
+}
+
+impl Display for Budget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count(count) => write!(f, "{count}"),
+            Self::Percentage(percentage) => write!(f, "{percentage}%"),
+        }
+    }
+}
+
+/// A budget of `--check` for the symbols whose name matches a regular expression.
+#[derive(Debug, Clone)]
+pub struct SymbolBudget {
+    /// The expression the names of the symbols match.
+    pub regex: Regex,
+    /// The largest increase allowed for each of them.
+    pub budget: Budget,
+}
+
+impl FromStr for SymbolBudget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((regex, budget)) = s.rsplit_once("=>") else {
+            bail!("No '=>' in symbol budget");
+        };
+        Ok(Self {
+            regex: Regex::new(regex)?,
+            budget: budget.parse()?,
+        })
+    }
+}
+
+impl Display for SymbolBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Options for parsing input files, taken from [`Args`].
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions<'a> {
+    /// The normalization passes to run on the symbol names.
+    pub normalize: &'a [Normalize],
+    /// The replacements to perform in the symbol names.
+    pub replacements: &'a [StringReplacement],
+    /// The regular expression replacements to perform in the symbol names, after
+    /// [`Self::replacements`].
+    pub regex_replacements: &'a [RegexReplacement],
+    /// Whether malformed lines are skipped with a warning instead of failing the parse.
+    pub lenient: bool,
+}
+
+impl ParseOptions<'_> {
+    /// Normalize the given symbol name, then perform the symbol name replacements on it.
+    pub fn rewrite_symbol(&self, symbol: String) -> String {
+        let symbol = normalize(symbol, self.normalize);
+        let symbol = self
+            .replacements
+            .iter()
+            .fold(Cow::Owned(symbol), |symbol, replacement| {
+                replacement.perform(symbol)
+            });
+        self.regex_replacements
+            .iter()
+            .fold(symbol, |symbol, replacement| replacement.perform(symbol))
+            .into_owned()
+    }
+}
+
 /// A tool to help keep track of performance changes over time.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -302,17 +759,236 @@ pub struct Args {
     pub csv_all_data: bool,
     /// Column names for the CSV export. Use multiple times to specify multiple names.
     ///
-    /// There must be as many names as there are `callgrind_annotate` files given as argument
-    /// (i.e. this does not account for columns from CSV files, which may already have their own
-    /// names.). Use --csv-names "Name1" --csv-names "Name2" for names with spaces or commas.
+    /// There must be as many names as there are inputs other than CSV without a name (as in
+    /// `<name>=<path>`) given as argument (i.e. this does not account for columns from CSV files,
+    /// which may already have their own names.). Use --csv-names "Name1" --csv-names "Name2" for
+    /// names with spaces or commas.
     #[arg(long, action = clap::ArgAction::Append)]
     pub csv_names: Vec<String>,
+    /// Path to an output file in which to write the runs and symbols as JSON.
+    ///
+    /// Every event is exported, along with the metadata, kind, totals and threshold of each run,
+    /// and the file and object of each symbol. The file can be loaded back as an input.
+    #[arg(long, default_value_t)]
+    pub json_export: String,
+    /// The layout of the JSON export.
+    ///
+    /// Accepted values are:
+    ///   * `json`: A single JSON document (default).
+    ///   * `ndjson`: One JSON object per line, the runs on the first line, then each symbol on a
+    ///     line of its own, to be processed line by line.
+    #[arg(long, default_value = "json")]
+    pub json_format: JsonFormat,
+    /// Skip malformed lines in input files instead of failing.
+    ///
+    /// A warning with the file and line number is printed for each skipped line.
+    #[arg(long, default_value_t = false)]
+    pub lenient: bool,
+    /// In which order the files of a directory or glob pattern given as input are loaded.
+    ///
+    /// Accepted values are:
+    ///   * `natural`: By file name, comparing numbers by value (`run2` before `run10`) (default).
+    ///   * `mtime`: By modification time, oldest first.
+    ///   * `pid`: By PID of the profiled process, as recorded in the file (`pid:` line of raw
+    ///     files, `Profiled target:` of `callgrind_annotate` files) or at the end of its name
+    ///     (`callgrind.out.<pid>`).
+    ///   * `field:<key>`: By the value of the `<key>:` line of the header (e.g. `field:Timerange`,
+    ///     `field:cmd`), with numbers compared by value. Files without it come last.
+    ///
+    /// Inputs given one by one are loaded in the order they are given.
+    #[arg(long, default_value = "natural")]
+    pub input_order: InputOrder,
+    /// Load several captures of the same execution as a single run, `<name>=<input>,<input>...`.
+    ///
+    /// This is meant for `callgrind_annotate` outputs of the same profile at different thresholds
+    /// (e.g. `full=low.cg,medium.cg,high.cg`): the run holds every symbol listed by any of them,
+    /// with the threshold of the most complete one. Symbols listed by several inputs must have
+    /// the same costs, and so must the totals. Each conflict is reported with a warning, or an
+    /// error with `--strict`.
+    ///
+    /// It also merges the self and inclusive outputs of the same profile (e.g.
+    /// `before=before.cg,before_incl.cg`, the latter from `callgrind_annotate --inclusive=yes`).
+    ///
+    /// Each input is `[<kind>:]<path>`, and may be a directory or a glob pattern. Merged runs come
+    /// after the positional inputs, in the order they are given. This option can be repeated.
+    #[arg(long, action = clap::ArgAction::Append)]
+    pub merge_into: Vec<MergeInto>,
+    /// Load several samples of the same version as a single run, `<name>=<input>,<input>...`.
+    ///
+    /// This is meant for repeated runs of a benchmark that vary from run to run (hashing seeds,
+    /// allocators, threads), e.g. `v1=a1.cg,a2.cg,a3.cg`. The run shows the mean cost of each
+    /// symbol over the samples, and keeps their mean, min, max and standard deviation (see
+    /// `--show-metadata` and `--json-export`). A symbol that a sample does not list costs 0 in
+    /// it.
+    ///
+    /// When both a run and its reference are sample groups of 2 samples or more, each compared
+    /// column ends with a flag telling whether the difference of the main cost (self, or
+    /// inclusive if only those were loaded) is significant (`*`) or noise (`~`), as per Welch's
+    /// t-test at the level of `--significance`.
+    ///
+    /// Each input is `[<kind>:]<path>`, and may be a directory or a glob pattern. Sample groups
+    /// come after the positional inputs and `--merge-into` runs, in the order they are given.
+    /// This option can be repeated.
+    #[arg(long, action = clap::ArgAction::Append)]
+    pub sample_group: Vec<MergeInto>,
+    /// The significance level of the comparison of sample groups (see `--sample-group`): a
+    /// difference is significant if noise alone gives one at least as large with a lower
+    /// probability.
+    #[arg(long, default_value_t = 0.05)]
+    pub significance: f64,
+    /// How to load the parts and threads of the same process.
+    ///
+    /// Callgrind writes a file per part of the profile with `--dump-every-bb` or
+    /// `callgrind_control -d`, and per thread with `--separate-threads=yes`. Inputs are of the
+    /// same process if they have the same PID and command line.
+    ///
+    /// Accepted values are:
+    ///   * `separate`: One run per input (default). The runs of the same process are labeled
+    ///     with their part and thread (`p2 t1` for part 2 of thread 1).
+    ///   * `sum`: One run per process, named after its first input, with the costs of all its
+    ///     parts and threads added up.
+    ///   * `command`: One run per command line, named after its first input, with the costs of
+    ///     all its processes added up. Only the files of the same argument (e.g. a directory) are
+    ///     added up. This gives a whole-program view of the profiles of `--trace-children=yes`,
+    ///     one per process.
+    #[arg(long, default_value = "separate")]
+    pub parts: Parts,
+    /// Keep the processes, parts and threads added up by `--parts sum` or `--parts command`.
+    ///
+    /// Each one gets a column of its own after the run it is added up into, named after its PID
+    /// (and part and thread). These columns show costs as they are, rather than compared to the
+    /// reference, and are exported as such in CSV.
+    #[arg(long, default_value_t = false)]
+    pub parts_breakdown: bool,
+    /// Fail, instead of warning, when runs were not produced alike.
+    ///
+    /// Runs are expected to come from the same target (command line), with the same recorded
+    /// events, the same `callgrind_annotate` thresholds and the same Valgrind version, as far as
+    /// their headers tell. The inputs of a `--merge-into` run are expected to agree on their
+    /// costs.
+    #[arg(long, default_value_t = false)]
+    pub strict: bool,
+    /// Show how each run was produced (data file, creator, command, PID, parts, threads, events,
+    /// thresholds and trigger, as far as its header tells) before the comparison.
+    #[arg(long, default_value_t = false)]
+    pub show_metadata: bool,
+    /// Check the runs against budgets, for continuous integration, and exit with a code telling
+    /// the outcome.
+    ///
+    /// Each compared profile run is checked against its reference (see `--relative-to`), for the
+    /// first event of `--event`. The costs that increased beyond their budget
+    /// (`--max-total-increase`, `--max-symbol-increase`, `--symbol-budget`) are listed after the
+    /// comparison, and so is the total if it decreased beyond `--improvement-threshold`.
+    ///
+    /// Exit codes are:
+    ///   * `0`: Every cost is within its budget.
+    ///   * `1`: An input could not be loaded (`2` for invalid arguments).
+    ///   * `3`: A cost increased beyond its budget (regression).
+    ///   * `4`: No cost increased beyond its budget, but the total decreased beyond
+    ///     `--improvement-threshold` (update the baseline).
+    #[arg(long, default_value_t = false)]
+    pub check: bool,
+    /// The largest increase of the total cost allowed by `--check`, as a count (`1000`) or a
+    /// percentage of the reference total (`2.5%`).
+    #[arg(long)]
+    pub max_total_increase: Option<Budget>,
+    /// The largest increase of the cost of each symbol allowed by `--check`, as a count or a
+    /// percentage of its reference cost.
+    ///
+    /// A symbol whose reference cost is 0, such as a new one, exceeds any percentage as soon as
+    /// it costs something. A symbol left out of the reference by its threshold is checked against
+    /// the most it may have cost there (e.g. 1% of the total at `Thresholds: 99`).
+    #[arg(long)]
+    pub max_symbol_increase: Option<Budget>,
+    /// The largest increase of the cost of the symbols matching a regular expression allowed by
+    /// `--check`, as `pattern=>budget` (e.g. `^hashbrown::=>5%`), split at the last `=>`.
+    ///
+    /// Patterns are matched against the names as shown, and are not anchored. Every budget whose
+    /// pattern a symbol matches applies, instead of `--max-symbol-increase`, which only applies
+    /// to the symbols matching no pattern. This option can be repeated.
+    #[arg(long, action = clap::ArgAction::Append)]
+    pub symbol_budget: Vec<SymbolBudget>,
+    /// The decrease of the total cost beyond which `--check` exits with the improvement code, as
+    /// a count or a percentage of the reference total, so that the baseline gets updated.
+    #[arg(long)]
+    pub improvement_threshold: Option<Budget>,
+    /// How to aggregate symbols.
+    ///
+    /// Accepted values are:
+    ///   * `function`: Add up symbols with the same function name (default).
+    ///   * `file`: Add up all symbols of each source file.
+    ///   * `object`: Add up all symbols of each object (binary or shared library).
+    ///   * `file:function`: Tell apart functions with the same name in different files.
+    ///   * `object:function`: Tell apart functions with the same name in different objects.
+    ///
+    /// This applies to every input. CSV inputs do not keep the files and objects of their
+    /// symbols, nor do JSON exports of symbols grouped without them: loading them fails unless
+    /// symbols are grouped by function.
+    #[arg(long, default_value = "function")]
+    pub group_by: GroupBy,
+    /// Put the symbols that are only an address (`0x0000000000001070`), as in stripped or JIT
+    /// code, into a single `<unresolved in OBJECT>` row per object.
+    ///
+    /// Their addresses change between builds, and would otherwise show as many rows dropping to 0
+    /// and appearing. Objects are named after their file name, or `???` if unknown (as for CSV
+    /// inputs). This applies before `--group-by`.
+    #[arg(long, default_value_t = false)]
+    pub group_unresolved: bool,
+    /// Normalization passes to run on the symbol names, as a comma-separated list, so that the
+    /// same function has the same name across builds.
+    ///
+    /// Accepted values are:
+    ///   * `demangle`: Demangle raw mangled names (Rust legacy and v0, Itanium C++).
+    ///   * `hashes`: Strip the hashes of Rust names (`::h<hash>` suffixes, `[<hash>]` crate
+    ///     disambiguators).
+    ///   * `closures`: Give all closures of a function the same name (`{{closure}}` and
+    ///     `{closure#<index>}` become `{closure}`).
+    ///   * `generics`: Collapse generic parameters (`Vec<u8>` becomes `Vec<_>`).
+    ///
+    /// Passes run in this order, before `--string-replace`. Symbols that end up with the same
+    /// name are added up.
+    #[arg(long, num_args=0.., value_delimiter=',')]
+    pub normalize: Vec<Normalize>,
+    /// A file of symbols that were renamed or moved, one `old=>new` per line.
+    ///
+    /// The rows of both names are merged into a single row, named after the new name and marked
+    /// with the old one (`new (was old)`). A run that lists one of the names only gives its
+    /// cost. Names are as shown, after `--normalize`, replacements and `--group-by`. Empty
+    /// lines and lines starting with `#` are ignored.
+    #[arg(long)]
+    pub aliases: Option<String>,
+    /// Propose symbols that may have been renamed, before the comparison.
+    ///
+    /// A symbol that is gone by the last run is proposed as renamed to one that is not there in
+    /// the first run when their names are similar, segment by segment of their `::` path, and
+    /// their costs close. Proposals are written as lines of an `--aliases` file, which merges
+    /// them once confirmed.
+    #[arg(long, default_value_t = false)]
+    pub detect_renames: bool,
     /// A replacement to perform in the symbol names.
     ///
     /// The replacement has the form `foo/bar` and will replace any occurence of `foo` within the
     /// symbol name by `bar`. This option can be repeated any number of times.
     #[arg(long, num_args=0..)]
     pub string_replace: Vec<StringReplacement>,
+    /// A regular expression replacement to perform in the symbol names.
+    ///
+    /// The replacement has the form `pattern=>replacement`, split at the first `=>`. Every match
+    /// of the pattern within the symbol name is replaced, and the replacement may refer to
+    /// capture groups as `$1` or `${name}` (`$$` for a literal `$`). Patterns are not anchored:
+    /// use `^` and `$` to match whole names, e.g. `^<impl .* for (\w+)>::(\w+)$=>$1::$2`.
+    ///
+    /// Replacements run after `--normalize` and `--string-replace`, in the order they are given,
+    /// each on the result of the previous one. This option can be repeated any number of times.
+    #[arg(long, action = clap::ArgAction::Append)]
+    pub regex_replace: Vec<RegexReplacement>,
+    /// A file of regular expression replacements, one `pattern=>replacement` per line, as for
+    /// `--regex-replace`.
+    ///
+    /// Empty lines and lines starting with `#` are ignored. These replacements run before those
+    /// of `--regex-replace`, in the order of the file.
+    #[arg(long)]
+    pub regex_replace_file: Option<String>,
     /// Path to an output file in which to write a graph of the IR values. Currently unsupported.
     #[arg(long, default_value_t)]
     pub export_graph: String,
@@ -332,54 +1008,118 @@ pub struct Args {
     ///   * `percentagediff`: The percentage/ratio of ir count with respect to [`relative_to`].
     ///   * `ircountdiff`: The IR count difference with respect to [`relative_to`].
     ///   * `all`: `ircountdiff` + `percentagediff` + `ircount`
+    ///   * `inclircount`, `inclpercentagediff`, `inclircountdiff`: The same, for inclusive costs.
+    ///     Inclusive costs are loaded from raw callgrind files and from the output of
+    ///     `callgrind_annotate --inclusive=yes`.
+    ///
+    /// If only inclusive costs were loaded, the columns show inclusive costs.
     ///
     /// Any value re-specified will be ignored. `all` has precedence. To show all columns in a
     /// different order than `all`, specify each column individually but not `all`.
     #[arg(long, num_args=0.., value_delimiter=',')]
     pub show: Vec<Show>,
-    /// `callgrind_annotate` files or CSV file. Positional arguments.
+    /// The events to show, sort by and export, as a comma-separated list (e.g. `Ir,Dr,D1mr`).
     ///
-    /// If the file name ends with `.csv` (case-insensitive), then the argument will be interpreted
-    /// as a csv file where each row is a symbol, each column a run and each cell an IR count.
+    /// Events are the ones recorded in the inputs (see `Events recorded:` in `callgrind_annotate`
+    /// files). Sorting uses the first event. When several events are given, each one gets its own
+    /// set of columns, side by side. Defaults to `Ir`, or to the first recorded event if `Ir` was
+    /// not recorded. The costs of an event are unknown in the runs that did not record it.
+    #[arg(long, num_args=0.., value_delimiter=',')]
+    pub event: Vec<String>,
+    /// Show the annotated source of the given file instead of the symbols.
+    ///
+    /// Each line of source is shown with its cost in the reference run, and its difference in
+    /// the other runs, for the first event of `--event`. Per-line costs are read from the
+    /// annotated source of `callgrind_annotate` files (`--auto=yes`). The file may be given by a
+    /// suffix of its path (e.g. `main.c` for `src/main.c`).
+    #[arg(long, default_value_t)]
+    pub source: String,
+    /// `callgrind_annotate` files, raw `callgrind.out` files, CSV or JSON files. Positional
+    /// arguments.
+    ///
+    /// Each input is `[<name>=][<kind>:]<path>`. The name is that of the column of the run, and
+    /// takes precedence over `--csv-names`. The kind is one of `csv`, `json`, `annotate`
+    /// (`callgrind_annotate` or former `cg_annotate`), `raw` (`callgrind.out`), `cg-annotate` and
+    /// `cg-raw` (`cachegrind.out`). Without a kind, it is detected from the content of the file as
+    /// follows.
+    ///
+    /// If the first line has commas and no `callgrind_annotate` marker, then the argument will be
+    /// interpreted as a csv file where each row is a symbol, each column a run and each cell an IR
+    /// count.
     /// The first row will be interepreted as a header if and only if the first cell contains
     /// `"name"` and the second cell cannot be parsed as an integer.
+    /// If every column of the header ends with `_ir`, `_diff` or `_pct`, as exported with
+    /// `--csv-all-data` and the like, only the `_ir` columns are loaded.
+    ///
+    /// Files starting with `{` are interpreted as JSON or NDJSON files written by `--json-export`.
+    ///
+    /// Files starting with `# callgrind format` (or a `version:` line) are interpreted as raw
+    /// `callgrind.out.<pid>` files, and files starting with `desc:` or `cmd:` lines as raw
+    /// `cachegrind.out.<pid>` files. Files starting with a `-- Metadata` section are interpreted
+    /// as an output from `cg_annotate`. Otherwise, interpret the file as an output from
+    /// `callgrind_annotate` (or from `cg_annotate` before Valgrind 3.22, which looks the same).
+    ///
+    /// A directory stands for all the files in it, and a glob pattern (e.g. `'nightly/*.cg'`) for
+    /// the files it matches. They are loaded in the order of `--input-order`, and the columns are
+    /// named after the part of the file names that differs.
     ///
-    /// Otherwise, interpret the file as an output from `callgrind_annotate`.
+    /// `-` reads the standard input, and may be given once. Gzip, zstd and xz compressed inputs
+    /// are decompressed before their format is detected, whatever their extension.
     ///
     /// Columns are loaded in the order they are positioned. One can have columns from a run
     /// (`callgrind_annotate`), then a CSV and then another run. The columns of the CSV file will
     /// be surrounded by the columns of the runs.
-    pub inputs: Vec<String>,
+    pub inputs: Vec<InputSpec>,
 }
 
 impl Args {
+    /// Return the options used to parse input files.
+    pub fn parse_options(&self) -> ParseOptions<'_> {
+        ParseOptions {
+            normalize: &self.normalize,
+            replacements: &self.string_replace,
+            regex_replacements: &self.regex_replace,
+            lenient: self.lenient,
+        }
+    }
+
     /// Perform final check for values in the arguments.
     ///
     /// # Returns
-    /// If all arguments are well-formed, returns an `Ok`. Otherwise, returns an `Err`.
+    /// If all arguments are well-formed, returns an `Ok`. Otherwise, returns an `Err`, which is
+    /// reported as an invalid argument.
     pub fn validated(mut self) -> Result<Self> {
-        self.check_csv_names_count()?;
         self.check_input_length()?;
+        self.check_stdin_count()?;
+        if !(0.0..=1.0).contains(&self.significance) {
+            bail!("--significance must be between 0 and 1");
+        }
+        self.check_budgets()?;
         self.sanitize_show();
         Ok(self)
     }
 
-    /// Check that the number of names in `csv_names` matches the number of runs in `inputs`.
-    fn check_csv_names_count(&self) -> Result<()> {
-        if !self.csv_names.is_empty() {
-            let runs_count = self
-                .inputs
-                .iter()
-                .filter(|file| {
-                    !Path::new(file)
-                        .extension()
-                        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
-                })
-                .count();
-            if runs_count != self.csv_names.len() {
-                bail!("Mismatch between `csv-names` count {} and number of callgrind files {runs_count}", self.csv_names.len());
+    /// Read the replacements of `regex_replace_file`, if any, before those of `regex_replace`.
+    ///
+    /// This is done after [`Self::validated`], as an error reading the file is an input error.
+    pub fn load_regex_replace_file(&mut self) -> Result<()> {
+        let Some(path) = &self.regex_replace_file else {
+            return Ok(());
+        };
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("Could not read {path}"))?;
+        let mut replacements = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
             }
+            let replacement = line
+                .parse::<RegexReplacement>()
+                .with_context(|| format!("{path}:{}: invalid regex replacement", i + 1))?;
+            replacements.push(replacement);
         }
+        replacements.append(&mut self.regex_replace);
+        self.regex_replace = replacements;
         Ok(())
     }
 
@@ -401,9 +1141,41 @@ impl Args {
         }
     }
 
-    /// Make sure we are provided with 1 positional argument at least.
+    /// Make sure that budgets are given with `--check`, and the other way around.
+    ///
+    /// `--improvement-threshold` counts as a budget, as improvements have an exit code of their
+    /// own.
+    fn check_budgets(&self) -> Result<()> {
+        let has_budgets = self.max_total_increase.is_some()
+            || self.max_symbol_increase.is_some()
+            || !self.symbol_budget.is_empty()
+            || self.improvement_threshold.is_some();
+        if self.check && !has_budgets {
+            bail!(
+                "--check needs a budget: --max-total-increase, --max-symbol-increase, \
+                 --symbol-budget or --improvement-threshold"
+            );
+        }
+        if !self.check && has_budgets {
+            bail!("Budgets are only used with --check");
+        }
+        Ok(())
+    }
+
+    /// Make sure that the standard input is read once at most.
+    fn check_stdin_count(&self) -> Result<()> {
+        let merged = self.merge_into.iter().chain(&self.sample_group);
+        let merged = merged.flat_map(|merge| &merge.inputs);
+        if self.inputs.iter().chain(merged).filter(|input| input.path == STDIN_PATH).count() > 1 {
+            bail!("The standard input (`{STDIN_PATH}`) can be given once only");
+        }
+        Ok(())
+    }
+
+    /// Make sure we are provided with 1 positional argument, `--merge-into` or `--sample-group` at
+    /// least.
     fn check_input_length(&self) -> Result<()> {
-        if self.inputs.is_empty() {
+        if self.inputs.is_empty() && self.merge_into.is_empty() && self.sample_group.is_empty() {
             bail!("No input file")
         }
         Ok(())
@@ -0,0 +1,126 @@
+use crate::{
+    args::ParseOptions,
+    callgrind::{
+        add_symbols, is_separation_line, is_table_header, parse_costs, parse_header,
+        parse_source_blocks, parse_totals, warn_skipped, InputLines, ParseError, ParseErrorKind,
+    },
+    runs::{AnnotatedSymbol, Run},
+};
+
+/// Parse a line of the `File:function summary` table.
+///
+/// Files are listed with their total cost (`< <costs> <file>:`), followed by the cost of each of
+/// their functions (`<costs> <function>`). A file with a single function is listed on a single
+/// line (`< <costs> <file>:<function>`).
+///
+/// Returns the file that the following function lines belong to, if any, and the symbol of the
+/// line, if any.
+fn parse_summary_line(
+    line: &str,
+    n_events: usize,
+    current_file: Option<&str>,
+) -> Result<(Option<String>, Option<AnnotatedSymbol>), ParseErrorKind> {
+    if let Some(line) = line.trim_start().strip_prefix('<') {
+        let (costs, words) = parse_costs(line, n_events)?;
+        let location = words.join(" ");
+        // File names seldom contain `:`, unlike C++ or Rust symbols.
+        let Some((file, name)) = location.split_once(':') else {
+            return Err(ParseErrorKind::Unexpected {
+                expected: "a `file:` or `file:function` after the counts",
+                found: line.to_string(),
+            });
+        };
+        if name.is_empty() {
+            return Ok((Some(file.to_string()), None));
+        }
+        return Ok((
+            None,
+            Some(AnnotatedSymbol {
+                name: name.to_string(),
+                file: Some(file.to_string()),
+                costs,
+                ..Default::default()
+            }),
+        ));
+    }
+
+    let Some(file) = current_file else {
+        return Err(ParseErrorKind::Unexpected {
+            expected: "a `< file:` line before the functions of a file",
+            found: line.to_string(),
+        });
+    };
+    let (costs, words) = parse_costs(line, n_events)?;
+    Ok((
+        Some(file.to_string()),
+        Some(AnnotatedSymbol {
+            name: words.join(" "),
+            file: Some(file.to_string()),
+            costs,
+            ..Default::default()
+        }),
+    ))
+}
+
+/// Parse the output of `cg_annotate` (Valgrind 3.22 and later) and return a `Run` from it.
+///
+/// The output is made of `-- <Section>` sections. The totals are read from `-- Summary`, the
+/// symbols from `-- File:function summary` and the per-line costs from the `-- Annotated source
+/// file: <file>` sections, if any. Older versions of `cg_annotate` have the same layout as
+/// `callgrind_annotate` and are parsed by [`crate::callgrind::parse`].
+///
+/// `file` is only used to report errors. With [`ParseOptions::lenient`], malformed lines are
+/// skipped with a warning.
+pub fn parse<R: std::io::BufRead>(
+    input: R,
+    file: &str,
+    options: &ParseOptions,
+) -> Result<Run, ParseError> {
+    const TABLE_HEADER: &str = "a table header starting with the first event shown";
+
+    let mut lines = InputLines::new(input, file);
+    let mut run = Run::new();
+
+    parse_header(&mut lines, &mut run)?;
+    run.below_threshold = run.metadata.below_threshold(false);
+    let first_event = run.events[0].clone();
+    let n_events = run.events.len();
+    let is_header = |line: &str| is_table_header(line, &first_event);
+
+    // The header of the summary is followed by an empty line, then the totals.
+    lines.find_line("the `-- Summary` section", |line| line.trim() == "-- Summary")?;
+    lines.find_line(TABLE_HEADER, is_header)?;
+    lines.expect("an empty line")?;
+    let totals = parse_totals(&mut lines, n_events, options)?;
+
+    lines.find_line("the `-- File:function summary` section", |line| {
+        line.trim() == "-- File:function summary"
+    })?;
+    lines.find_line(TABLE_HEADER, is_header)?;
+
+    // Files are separated by empty lines, and the table ends with a separation line.
+    let mut symbols = Vec::new();
+    let mut current_file = None;
+    for line in lines.by_ref() {
+        let (i, line) = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if is_separation_line(&line) {
+            break;
+        }
+        match parse_summary_line(&line, n_events, current_file.as_deref()) {
+            Ok((file, symbol)) => {
+                current_file = file;
+                symbols.extend(symbol);
+            }
+            Err(kind) if options.lenient => warn_skipped(&ParseError::new(file, i, kind)),
+            Err(kind) => return Err(ParseError::new(file, i, kind)),
+        }
+    }
+
+    parse_source_blocks(lines, file, n_events, options, &mut run)?;
+    add_symbols(&mut run, symbols, totals, options);
+
+    Ok(run)
+}
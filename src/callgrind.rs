@@ -1,44 +1,200 @@
-use std::borrow::Cow;
+use std::fmt::Display;
 
 use itertools::Itertools;
 
-use crate::{args::StringReplacement, runs::Run};
+use crate::{
+    args::ParseOptions,
+    runs::{AnnotatedLine, AnnotatedSymbol, LoadedCosts, Run, RunKind, RunMetadata},
+};
 
-/// Parse the total IR line.
+/// An error found while parsing an input file.
+#[derive(Debug)]
+pub struct ParseError {
+    /// The file that was being parsed.
+    pub file: String,
+    /// The 1-based number of the line at which the error was found.
+    pub line: usize,
+    /// What went wrong.
+    pub kind: ParseErrorKind,
+}
+
+/// What went wrong while parsing an input file.
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The line did not match what was expected.
+    Unexpected {
+        /// What was expected at that line.
+        expected: &'static str,
+        /// The line that was found instead.
+        found: String,
+    },
+    /// The end of the file was reached while expecting something else.
+    UnexpectedEof {
+        /// What was expected at that line.
+        expected: &'static str,
+    },
+    /// A count could not be parsed.
+    InvalidCount(String),
+    /// A JSON input did not match its schema.
+    InvalidJson(String),
+}
+
+impl ParseError {
+    /// Create a new error at the given line of the given file.
+    pub fn new(file: &str, line: usize, kind: ParseErrorKind) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            kind,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: ", self.file, self.line)?;
+        match &self.kind {
+            ParseErrorKind::Io(error) => write!(f, "{error}"),
+            ParseErrorKind::Unexpected { expected, found } => {
+                write!(f, "expected {expected}, found {found:?}")
+            }
+            ParseErrorKind::UnexpectedEof { expected } => {
+                write!(f, "expected {expected}, found end of file")
+            }
+            ParseErrorKind::InvalidCount(count) => write!(f, "invalid count {count:?}"),
+            ParseErrorKind::InvalidJson(error) => write!(f, "invalid JSON: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Report an error that `--lenient` allows us to skip over.
+pub fn warn_skipped(error: &ParseError) {
+    eprintln!("warning: {error} (skipped)");
+}
+
+/// Parse a count as printed by `callgrind_annotate`.
 ///
-/// This line is just after the `Ir` block and starts with the total `Ir` count. Numbers are
-/// "delimited" with commas since they are large (e.g.: 14,418,621,168).
-fn parse_total_ir_line(line: &str) -> u64 {
-    let word = line.trim().split(' ').next().unwrap();
-    let count = word
+/// Numbers are "delimited" with commas since they are large (e.g.: 14,418,621,168). A count of 0
+/// may be printed as a `.`. Counts are negative in the difference of two profiles (`cg_diff`).
+fn parse_count(word: &str) -> Result<i64, ParseErrorKind> {
+    if word == "." {
+        return Ok(0);
+    }
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word.strip_prefix('+').unwrap_or(word)),
+    };
+    if !digits.starts_with(|c: char| c.is_ascii_digit())
+        || !digits.chars().all(|c| c.is_ascii_digit() || c == ',')
+    {
+        return Err(ParseErrorKind::InvalidCount(word.to_string()));
+    }
+    digits
         .chars()
         // This filter ignore commas.
         .filter_map(|c| c.to_digit(10))
-        // This is akin to `str::parse::<u64>`.
-        .fold(0, |sum, digit| sum * 10 + u64::from(digit));
-    count
+        // This is akin to `str::parse::<i64>`.
+        .try_fold(0i64, |sum, digit| {
+            let digit = i64::from(digit);
+            sum.checked_mul(10)?
+                .checked_add(if negative { -digit } else { digit })
+        })
+        .ok_or_else(|| ParseErrorKind::InvalidCount(word.to_string()))
+}
+
+/// Parse the leading costs of a line, one for each of the `n_events` shown events.
+///
+/// Each count may be followed by a percentage in parentheses. Returns the costs and the remaining
+/// words of the line.
+pub fn parse_costs(line: &str, n_events: usize) -> Result<(Vec<i64>, Vec<&str>), ParseErrorKind> {
+    // We ignore empty words (leading and trailing spaces as well).
+    let mut words = line.trim().split(' ').filter(|word| !word.is_empty()).peekable();
+    let mut costs = Vec::with_capacity(n_events);
+    for _ in 0..n_events {
+        costs.push(parse_count(words.next().unwrap_or_default())?);
+        // We then skip until the word ends with `)`, effectively skipping over the percentage.
+        // There may be spaces in the percentage (`( 2.22%)`).
+        if words.peek().is_some_and(|word| word.starts_with('(')) {
+            for word in words.by_ref() {
+                if word.ends_with(')') {
+                    break;
+                }
+            }
+        }
+    }
+    Ok((costs, words.collect()))
+}
+
+/// Parse the leading costs of a line of annotated source, one for each of the `n_events` shown
+/// events.
+///
+/// Unlike [`parse_costs`], the rest of the line is kept as is, since it is source code. Returns
+/// the costs, the byte offset at which the costs (and their percentages) end, and whether any
+/// count was printed (as opposed to only `.`).
+fn parse_source_costs(
+    line: &str,
+    n_events: usize,
+) -> Result<(Vec<i64>, usize, bool), ParseErrorKind> {
+    let mut costs = Vec::with_capacity(n_events);
+    let mut end = 0;
+    let mut counted = false;
+    for _ in 0..n_events {
+        let rest = line[end..].trim_start_matches(' ');
+        let start = line.len() - rest.len();
+        end = rest.find(' ').map_or(line.len(), |i| start + i);
+        let word = &line[start..end];
+        counted |= word != ".";
+        costs.push(parse_count(word)?);
+        // A count may be followed by its percentage. Source code may start with a `(` as well,
+        // hence the check for the `%)`.
+        let rest = line[end..].trim_start_matches(' ');
+        if let Some(close) = rest.find(')').filter(|&close| {
+            rest.starts_with('(') && rest[..=close].ends_with("%)")
+        }) {
+            end = line.len() - rest.len() + close + 1;
+        }
+    }
+    Ok((costs, end, counted))
 }
 
-/// Parse an IR line for a particular symbol.
+/// Parse the total line.
+///
+/// This line is just after the header of the totals block and starts with the total count of each
+/// event, followed by `PROGRAM TOTALS`.
+fn parse_total_line(line: &str, n_events: usize) -> Result<Vec<i64>, ParseErrorKind> {
+    let (costs, words) = parse_costs(line, n_events)?;
+    if words.join(" ") != "PROGRAM TOTALS" {
+        return Err(ParseErrorKind::Unexpected {
+            expected: "the `PROGRAM TOTALS` line",
+            found: line.to_string(),
+        });
+    }
+    Ok(costs)
+}
+
+/// Parse a cost line for a particular symbol.
 ///
 /// The line is of the form:
 /// ```no_compile
-/// <ir> (xx.xx%) <loc>:<sym> [<file>]
+/// <ir> (xx.xx%) [<dr> (xx.xx%) ...] <loc>:<sym> [<object>]
 /// ```
 ///
 /// There may be leading spaces to `ir`, spaces in the percentage and even in `loc`.
-fn parse_fn_ir_line(line: &str) -> (String, u64) {
-    // We ignore empty words (leading and trailing spaces as well).
-    let mut words = line.trim().split(' ').filter(|word| !word.is_empty());
-    // First word is `<ir>`.
-    let ir_str = words.next().unwrap();
-    let ir_str = ir_str
-        .chars()
-        .filter_map(|c| c.to_digit(10))
-        .fold(0, |sum, digit| sum * 10 + u64::from(digit));
+fn parse_fn_line(line: &str, n_events: usize) -> Result<AnnotatedSymbol, ParseErrorKind> {
+    let (costs, words) = parse_costs(line, n_events)?;
+    let mut words = words.into_iter();
 
-    // We then skip until the word ends with `)`, effectively skipping over the percentage.
-    let words = words.skip_while(|word| !word.ends_with(')')).skip(1);
     // We then take words until one starts with `[`. This takes both `<loc>:<sym>`.
     // Joining with space allows us to rebuild constructs such as:
     // ```
@@ -46,37 +202,487 @@ fn parse_fn_ir_line(line: &str) -> (String, u64) {
     //                           ^  ^
     //                      These spaces are a pain
     // ```
-    let loc = words.take_while(|word| !word.starts_with('[')).join(" ");
-    // We ignore every character until we reach the `:` that precedes `<sym>` and consume that one
-    // as well. Hurray, we found our symbol.
-    let loc = loc.chars().skip_while(|c| *c != ':').skip(1).collect();
+    let loc = words
+        .by_ref()
+        .take_while_inclusive(|word| !word.starts_with('['))
+        .collect::<Vec<_>>();
+    let (loc, object) = match loc.split_last() {
+        Some((last, loc)) if last.starts_with('[') => {
+            // The object path may contain spaces as well.
+            let object = std::iter::once(*last).chain(words).join(" ");
+            (loc.join(" "), Some(object))
+        }
+        _ => (loc.join(" "), None),
+    };
+    let object = object.map(|object| {
+        object
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string()
+    });
 
-    (loc, ir_str)
+    // The `:` that precedes `<sym>` separates it from the file. Hurray, we found our symbol.
+    let Some((file, name)) = loc.split_once(':').filter(|(_, name)| !name.is_empty()) else {
+        return Err(ParseErrorKind::Unexpected {
+            expected: "a `file:function` after the counts",
+            found: line.to_string(),
+        });
+    };
+
+    Ok(AnnotatedSymbol {
+        name: name.to_string(),
+        file: Some(file.to_string()),
+        object,
+        costs,
+        inclusive: Vec::new(),
+        stats: None,
+    })
+}
+
+/// Whether the line is the header of a table, which starts with the name of the first event.
+///
+/// `cg_annotate` pads the name with underscores (`Ir______`).
+pub fn is_table_header(line: &str, first_event: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|word| word.trim_end_matches('_') == first_event)
+}
+
+/// Whether the costs of the function table are inclusive (`callgrind_annotate --inclusive=yes`).
+///
+/// This is read from the header if it tells (see [`RunMetadata::inclusive`]). Otherwise, self
+/// costs of distinct functions never add up to more than the program totals, whereas inclusive
+/// costs do as soon as a function calls another (`main` and its callers alone already account for
+/// about all of the totals). Without totals, there is no telling them apart.
+fn is_inclusive(
+    metadata: &RunMetadata,
+    symbols: &[AnnotatedSymbol],
+    totals: Option<&[i64]>,
+) -> bool {
+    metadata.inclusive.unwrap_or_else(|| {
+        totals.is_some_and(|totals| {
+            let sum = symbols.iter().map(|symbol| symbol.costs[0]).sum::<i64>();
+            sum > totals[0]
+        })
+    })
+}
+
+/// Return the file of the block of annotated source starting at that line, if it does.
+fn parse_source_header(line: &str) -> Option<&str> {
+    line.strip_prefix("-- Auto-annotated source:")
+        .or_else(|| line.strip_prefix("-- User-annotated source:"))
+        .or_else(|| line.strip_prefix("-- Annotated source file:"))
+        .map(str::trim)
+}
+
+/// Return the line number given by a `-- line <n> -----` line, if it is one.
+fn parse_source_line_marker(line: &str) -> Option<u32> {
+    line.strip_prefix("-- line ")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// A block of annotated source being parsed.
+struct SourceBlock {
+    /// The annotated file.
+    file: String,
+    /// The number of the next line of source.
+    next_line: u32,
+    /// The lines of the block: their number, costs, line of input and where the costs end.
+    lines: Vec<(u32, Vec<i64>, String, usize)>,
+    /// Where the source code starts in lines with a count, if one was seen.
+    text_column: Option<usize>,
+}
+
+impl SourceBlock {
+    /// Start a block for the given file.
+    fn new(file: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            next_line: 1,
+            lines: Vec::new(),
+            text_column: None,
+        }
+    }
+
+    /// Add the lines of the block to the run.
+    ///
+    /// All lines of a block are printed with the same column widths, but `.` counts have no
+    /// percentage, so where source code starts is only known from lines with counts. There are
+    /// two spaces in between the costs and the source.
+    fn finish(self, run: &mut Run) {
+        for (line, costs, input, end) in self.lines {
+            let text = match self.text_column {
+                Some(column) => input.get(column..).unwrap_or_default(),
+                None => input[end..].trim_start(),
+            };
+            run.lines.push(AnnotatedLine {
+                file: self.file.clone(),
+                line,
+                text: text.to_string(),
+                costs,
+            });
+        }
+    }
+}
+
+/// Parse the blocks of annotated source that follow the function table (with
+/// `callgrind_annotate --auto=yes`, or when files are given on the command line). `cg_annotate`
+/// annotates source the same way.
+///
+/// Each line of source is preceded by its costs. Calls (`=> file:function (1x)`) follow the line
+/// they are made from and are skipped, since their cost is already part of that line.
+pub fn parse_source_blocks(
+    lines: impl Iterator<Item = Result<(usize, String), ParseError>>,
+    file: &str,
+    n_events: usize,
+    options: &ParseOptions,
+    run: &mut Run,
+) -> Result<(), ParseError> {
+    let mut block: Option<SourceBlock> = None;
+    for line in lines {
+        let (i, line) = line?;
+        if let Some(source) = parse_source_header(&line) {
+            if let Some(block) = block.take() {
+                block.finish(run);
+            }
+            block = Some(SourceBlock::new(source));
+            continue;
+        }
+        let Some(current) = block.as_mut() else {
+            continue;
+        };
+        let started = !current.lines.is_empty();
+        if is_separation_line(&line) {
+            // The separation line after the header, or the end of the block.
+            if started {
+                block.take().unwrap().finish(run);
+            }
+            continue;
+        }
+        if line.trim().is_empty() || (!started && is_table_header(&line, &run.events[0])) {
+            continue;
+        }
+        if let Some(next_line) = parse_source_line_marker(&line) {
+            current.next_line = next_line;
+            continue;
+        }
+
+        match parse_source_costs(&line, n_events) {
+            Ok((costs, end, counted)) => {
+                if line[end..].trim_start().starts_with("=> ") {
+                    continue;
+                }
+                if counted {
+                    current.text_column = Some(current.text_column.unwrap_or(0).max(end + 2));
+                }
+                current.lines.push((current.next_line, costs, line, end));
+                current.next_line += 1;
+            }
+            // `callgrind_annotate` explains why a file is not annotated in place of its source.
+            Err(_) if !started => block = None,
+            Err(kind) if options.lenient => warn_skipped(&ParseError::new(file, i, kind)),
+            Err(kind) => return Err(ParseError::new(file, i, kind)),
+        }
+    }
+    if let Some(block) = block {
+        block.finish(run);
+    }
+    Ok(())
+}
+
+/// Whether the line is a `-----` separation line.
+pub fn is_separation_line(line: &str) -> bool {
+    line.starts_with("---")
+}
+
+/// The lines of an input file, numbered for error reporting.
+pub struct InputLines<'a, R> {
+    lines: std::iter::Enumerate<std::io::Lines<R>>,
+    /// The file being read.
+    file: &'a str,
+    /// The number of the last line read, to report errors at the end of the file.
+    last_line: usize,
+}
+
+impl<'a, R: std::io::BufRead> InputLines<'a, R> {
+    /// Read the lines of `input`, which is read from `file`.
+    pub fn new(input: R, file: &'a str) -> Self {
+        Self {
+            lines: input.lines().enumerate(),
+            file,
+            last_line: 0,
+        }
+    }
+
+    /// Return the next line and its number, or an error if the end of the file is reached while
+    /// `expected` was expected.
+    pub fn expect(&mut self, expected: &'static str) -> Result<(usize, String), ParseError> {
+        self.next().unwrap_or_else(|| {
+            Err(ParseError::new(
+                self.file,
+                self.last_line + 1,
+                ParseErrorKind::UnexpectedEof { expected },
+            ))
+        })
+    }
+
+    /// Skip lines until one for which `f` returns `true`, and return it.
+    pub fn find_line(
+        &mut self,
+        expected: &'static str,
+        f: impl Fn(&str) -> bool,
+    ) -> Result<(usize, String), ParseError> {
+        loop {
+            let (i, line) = self.expect(expected)?;
+            if f(&line) {
+                return Ok((i, line));
+            }
+        }
+    }
+
+    /// Return an error for the given line.
+    pub fn error(&self, line: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError::new(self.file, line, kind)
+    }
+}
+
+impl<R: std::io::BufRead> Iterator for InputLines<'_, R> {
+    type Item = Result<(usize, String), ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, line) = self.lines.next()?;
+        self.last_line = i + 1;
+        Some(
+            line.map(|line| (i + 1, line))
+                .map_err(|error| ParseError::new(self.file, i + 1, ParseErrorKind::Io(error))),
+        )
+    }
+}
+
+/// Read a line of the header into the metadata of a run, if it holds any.
+fn parse_metadata_line(line: &str, metadata: &mut RunMetadata) {
+    let value = |prefix: &str| {
+        line.strip_prefix(prefix)
+            .map(|value| value.trim().to_string())
+    };
+    if let Some(rest) = line.strip_prefix("Profile data file ") {
+        // `'callgrind.out.4242' (creator: callgrind-3.18.1)`
+        let (file, creator) = match rest.rsplit_once(" (creator: ") {
+            Some((file, creator)) => (file, Some(creator.trim_end_matches(')'))),
+            None => (rest, None),
+        };
+        metadata.data_file = Some(file.trim().trim_matches('\'').to_string());
+        metadata.creator = creator.map(str::to_string);
+    } else if let Some(target) = value("Profiled target:") {
+        // `./program arguments (PID 4242, part 1, thread 2)`
+        let (command, process) = match target.rsplit_once(" (PID ") {
+            Some((command, process)) => (command, process.trim_end_matches(')')),
+            None => (target.as_str(), ""),
+        };
+        metadata.command = Some(command.trim().to_string());
+        let mut process = process.split(", ");
+        metadata.pid = process.next().and_then(|pid| pid.parse().ok());
+        for field in process {
+            if let Some(part) = field.strip_prefix("part ").and_then(|part| part.parse().ok()) {
+                metadata.parts = vec![part];
+            } else if let Some(thread) =
+                field.strip_prefix("thread ").and_then(|thread| thread.parse().ok())
+            {
+                metadata.threads = vec![thread];
+            }
+        }
+    } else if let Some(command) = value("Command:") {
+        metadata.command = Some(command);
+    } else if let Some(file) = value("Data file:") {
+        metadata.data_file = Some(file);
+    } else if let Some(events) = value("Events recorded:") {
+        metadata.events_recorded = events.split_whitespace().map(str::to_string).collect();
+    } else if let Some(thresholds) = value("Thresholds:").or_else(|| value("Threshold:")) {
+        metadata.thresholds = Some(thresholds);
+    } else if let Some(trigger) = value("Trigger:") {
+        metadata.trigger = Some(trigger);
+    } else if let Some(inclusive) = value("Inclusive:") {
+        metadata.inclusive = Some(inclusive == "yes");
+    } else if let Some(invocation) = value("Invocation:") {
+        // The options of `callgrind_annotate` come before the profile data file, unlike the
+        // arguments of the profiled program, which other lines echo.
+        let inclusive = invocation
+            .split_whitespace()
+            .skip(1)
+            .take_while(|word| word.starts_with('-'))
+            .filter_map(|word| word.strip_prefix("--inclusive="))
+            .last();
+        if let Some(inclusive) = inclusive {
+            metadata.inclusive = Some(inclusive == "yes");
+        }
+    }
+}
+
+/// Read the header into the events, kind and metadata of `run`.
+///
+/// The header ends with the first empty or separation line after the `Events shown:` line, or
+/// with the end of the file. Differences of profiles (`cg_annotate` run on the output of
+/// `cg_diff`, or with `--diff`) are told apart by their `Files compared:` line.
+pub fn parse_header<R: std::io::BufRead>(
+    lines: &mut InputLines<R>,
+    run: &mut Run,
+) -> Result<(), ParseError> {
+    let (i, line) = loop {
+        let (i, line) = lines.expect("an `Events shown:` header line")?;
+        if line.starts_with("Files compared:") {
+            run.kind = RunKind::Delta;
+        }
+        if line.starts_with("Events shown:") {
+            break (i, line);
+        }
+        parse_metadata_line(&line, &mut run.metadata);
+    };
+    let events = line
+        .trim_start_matches("Events shown:")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if events.is_empty() {
+        return Err(lines.error(
+            i,
+            ParseErrorKind::Unexpected {
+                expected: "at least one event",
+                found: line,
+            },
+        ));
+    }
+    run.events = events;
+
+    // Thresholds come after the events shown.
+    for line in lines.by_ref() {
+        let (_, line) = line?;
+        if line.trim().is_empty() || is_separation_line(&line) {
+            break;
+        }
+        parse_metadata_line(&line, &mut run.metadata);
+    }
+    Ok(())
+}
+
+/// Parse the `PROGRAM TOTALS` line that is the next line of `lines`.
+///
+/// With [`ParseOptions::lenient`], a malformed line is skipped with a warning and `None` is
+/// returned.
+pub fn parse_totals<R: std::io::BufRead>(
+    lines: &mut InputLines<R>,
+    n_events: usize,
+    options: &ParseOptions,
+) -> Result<Option<Vec<i64>>, ParseError> {
+    let (i, line) = lines.expect("the `PROGRAM TOTALS` line")?;
+    match parse_total_line(&line, n_events) {
+        Ok(totals) => Ok(Some(totals)),
+        Err(kind) if options.lenient => {
+            warn_skipped(&lines.error(i, kind));
+            Ok(None)
+        }
+        Err(kind) => Err(lines.error(i, kind)),
+    }
+}
+
+/// Add the symbols read from a function table to the run.
+///
+/// Whether their costs are self or inclusive costs is read from the header or detected (see
+/// [`is_inclusive`]). Without totals, the totals are the sum of the symbols. Negative costs make
+/// the run a [`RunKind::Delta`], whose costs are taken as self costs.
+pub fn add_symbols(
+    run: &mut Run,
+    symbols: Vec<AnnotatedSymbol>,
+    totals: Option<Vec<i64>>,
+    options: &ParseOptions,
+) {
+    let negative = totals.iter().flatten().any(|&total| total < 0)
+        || symbols.iter().any(|symbol| symbol.costs.iter().any(|&cost| cost < 0));
+    if negative {
+        run.kind = RunKind::Delta;
+    }
+    // Differences are taken as self costs.
+    let inclusive =
+        run.kind == RunKind::Profile && is_inclusive(&run.metadata, &symbols, totals.as_deref());
+    run.totals = totals.unwrap_or_else(|| {
+        (0..run.events.len())
+            .map(|event| symbols.iter().map(|symbol| symbol.costs[event]).sum())
+            .collect()
+    });
+    run.loaded = if inclusive {
+        LoadedCosts::INCLUSIVE
+    } else {
+        LoadedCosts::SELF
+    };
+
+    for symbol in symbols {
+        let name = options.rewrite_symbol(symbol.name);
+        let file = symbol.file.as_deref();
+        let object = symbol.object.as_deref();
+        if inclusive {
+            run.add_inclusive_costs(&name, file, object, &symbol.costs);
+        } else {
+            run.add_costs(&name, file, object, &symbol.costs);
+        }
+    }
 }
 
 /// Parse a `callgrind_annotate` file and return a `Run` from it.
-pub fn parse<R: std::io::BufRead>(input: R, replacements: &[StringReplacement]) -> Run {
+///
+/// The output of `cg_annotate` before Valgrind 3.22 shares this layout, and is parsed the same.
+///
+/// The costs of every event listed in the `Events shown:` header are read. Whether they are self
+/// or inclusive costs is read from the header, or detected from the function table (see
+/// [`is_inclusive`]). `file` is only used to report errors.
+///
+/// The per-line costs of annotated source, if any, are read into [`Run::lines`].
+///
+/// With [`ParseOptions::lenient`], malformed symbol and source lines are skipped with a warning,
+/// and a malformed total line is replaced by the sum of the symbols.
+pub fn parse<R: std::io::BufRead>(
+    input: R,
+    file: &str,
+    options: &ParseOptions,
+) -> Result<Run, ParseError> {
+    const TABLE_HEADER: &str = "a table header starting with the first event shown";
+
+    let mut lines = InputLines::new(input, file);
     let mut run = Run::new();
-    let mut lines = input
-        .lines()
-        .map_while(std::result::Result::ok)
-        .skip_while(|line| !line.starts_with("Ir"))
-        .skip(2);
-    run.total_ir = parse_total_ir_line(&lines.next().unwrap());
-
-    for (symbol, ir) in lines
-        .skip_while(|line| !line.starts_with("Ir"))
-        .skip(2)
-        .take_while(|line| line.trim().chars().next().unwrap_or('\0').is_ascii_digit())
-        .map(|line| parse_fn_ir_line(&line))
-    {
-        let symbol = replacements
-            .iter()
-            .fold(Cow::Owned(symbol), |symbol, replacement| {
-                replacement.perform(symbol)
-            });
-        run.add_ir(&symbol, ir);
+
+    // The header lists the events shown in the tables.
+    parse_header(&mut lines, &mut run)?;
+    run.below_threshold = run.metadata.below_threshold(true);
+    let first_event = run.events[0].clone();
+    let n_events = run.events.len();
+
+    // The header of each table starts with the first event shown, and is followed by a separation
+    // line.
+    let is_header = |line: &str| is_table_header(line, &first_event);
+    lines.find_line(TABLE_HEADER, is_header)?;
+    lines.expect("a separation line")?;
+    let totals = parse_totals(&mut lines, n_events, options)?;
+
+    lines.find_line(TABLE_HEADER, is_header)?;
+    lines.expect("a separation line")?;
+
+    // The table ends with an empty line, or with the end of the file.
+    let mut symbols = Vec::new();
+    for line in lines.by_ref() {
+        let (i, line) = line?;
+        if line.trim().is_empty() || is_separation_line(&line) {
+            break;
+        }
+        match parse_fn_line(&line, n_events) {
+            Ok(symbol) => symbols.push(symbol),
+            Err(kind) if options.lenient => warn_skipped(&ParseError::new(file, i, kind)),
+            Err(kind) => return Err(ParseError::new(file, i, kind)),
+        }
     }
 
-    run
+    parse_source_blocks(lines, file, n_events, options, &mut run)?;
+    add_symbols(&mut run, symbols, totals, options);
+
+    Ok(run)
 }
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::{
+    args::ParseOptions,
+    callgrind::{warn_skipped, ParseError, ParseErrorKind},
+    runs::{LoadedCosts, Run, RunKind},
+};
+
+/// Compressed names of a single kind (`fn`, `fl` or `ob`).
+///
+/// Callgrind may give a name an id the first time it appears (`fn=(12) foo`) and then only refer
+/// to it by id (`fn=(12)`). Calls (`cfn=`, `cfl=`, `cob=`) share their id space with their
+/// non-call counterpart.
+#[derive(Default)]
+struct NameTable {
+    names: HashMap<u64, String>,
+}
+
+impl NameTable {
+    /// Resolve the value of a `xx=` line, recording the name if it is a definition.
+    ///
+    /// Names may start with a parenthesis as well (`(below main)`), which tells them apart from
+    /// compression ids is that the latter are numbers.
+    fn resolve(&mut self, value: &str) -> Result<String, ParseErrorKind> {
+        let value = value.trim();
+        let Some((id, name)) = value
+            .strip_prefix('(')
+            .and_then(|rest| rest.split_once(')'))
+            .and_then(|(id, name)| Some((id.parse::<u64>().ok()?, name.trim())))
+        else {
+            return Ok(value.to_string());
+        };
+        if name.is_empty() {
+            self.names
+                .get(&id)
+                .cloned()
+                .ok_or_else(|| ParseErrorKind::Unexpected {
+                    expected: "a name compression id defined earlier",
+                    found: value.to_string(),
+                })
+        } else {
+            self.names.insert(id, name.to_string());
+            Ok(name.to_string())
+        }
+    }
+}
+
+/// What the next cost line refers to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CostLine {
+    /// The cost is the self cost of the current function.
+    SelfCost,
+    /// The line follows a `calls=` line and holds the inclusive cost of the call.
+    Call,
+    /// The line follows a `jump=` or `jcnd=` line and holds no cost.
+    Jump,
+}
+
+/// Parse the costs of a cost line, skipping the `n_positions` leading positions.
+///
+/// Positions may be absolute (`16`, `0x1a`) or relative (`+2`, `-3`, `*`). Costs that are omitted
+/// at the end of the line are 0.
+fn parse_cost_line(line: &str, n_positions: usize) -> Result<Vec<i64>, ParseErrorKind> {
+    line.split_ascii_whitespace()
+        .skip(n_positions)
+        .map(|word| {
+            word.parse::<i64>()
+                .map_err(|_| ParseErrorKind::InvalidCount(word.to_string()))
+        })
+        .collect()
+}
+
+/// Whether the line is a cost line (as opposed to a specification line).
+fn is_cost_line(line: &str) -> bool {
+    line.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '+' | '-' | '*'))
+}
+
+/// The state of the parser in between lines.
+struct Parser {
+    /// The run being built.
+    run: Run,
+    functions: NameTable,
+    files: NameTable,
+    objects: NameTable,
+    /// The number of positions at the start of each cost line.
+    n_positions: usize,
+    summary: Option<Vec<i64>>,
+    totals: Option<Vec<i64>>,
+    computed_totals: Vec<i64>,
+    current_fn: Option<String>,
+    /// The file of the current function.
+    fn_file: Option<String>,
+    /// The file the next costs are attributed to. This changes with `fi=` and `fe=` lines, when
+    /// code from another file is inlined.
+    current_file: Option<String>,
+    current_object: Option<String>,
+    next_line: CostLine,
+}
+
+impl Parser {
+    /// Create a parser, ready to read the first line.
+    fn new() -> Self {
+        Self {
+            run: Run::new(),
+            functions: NameTable::default(),
+            files: NameTable::default(),
+            objects: NameTable::default(),
+            n_positions: 1,
+            summary: None,
+            totals: None,
+            computed_totals: Vec::new(),
+            current_fn: None,
+            fn_file: None,
+            current_file: None,
+            current_object: None,
+            next_line: CostLine::SelfCost,
+        }
+    }
+
+    /// Parse a single, non-empty, non-comment line.
+    fn parse_line(&mut self, line: &str, options: &ParseOptions) -> Result<(), ParseErrorKind> {
+        if is_cost_line(line) {
+            let kind = std::mem::replace(&mut self.next_line, CostLine::SelfCost);
+            if kind == CostLine::Jump {
+                return Ok(());
+            }
+            let costs = parse_cost_line(line, self.n_positions)?;
+            let Some(function) = &self.current_fn else {
+                return Err(ParseErrorKind::Unexpected {
+                    expected: "an `fn=` line before the first cost line",
+                    found: line.to_string(),
+                });
+            };
+            let file = self.current_file.as_deref();
+            let object = self.current_object.as_deref();
+            // The inclusive cost of a call is part of the inclusive cost of the caller.
+            self.run.add_inclusive_costs(function, file, object, &costs);
+            if kind == CostLine::SelfCost {
+                self.run.add_costs(function, file, object, &costs);
+                for (total, cost) in self.computed_totals.iter_mut().zip(&costs) {
+                    *total += cost;
+                }
+            }
+            return Ok(());
+        }
+
+        let Some((key, value)) = line.split_once([':', '=']) else {
+            return Err(ParseErrorKind::Unexpected {
+                expected: "a `key=value`, `key: value` or cost line",
+                found: line.to_string(),
+            });
+        };
+        match key {
+            "fn" => {
+                let name = self.functions.resolve(value)?;
+                self.current_fn = Some(options.rewrite_symbol(name));
+                self.current_file.clone_from(&self.fn_file);
+            }
+            "cfn" => {
+                self.functions.resolve(value)?;
+            }
+            "fl" => {
+                self.fn_file = Some(self.files.resolve(value)?);
+                self.current_file.clone_from(&self.fn_file);
+            }
+            "fi" | "fe" => self.current_file = Some(self.files.resolve(value)?),
+            "cfl" | "cfi" | "jfi" | "jfl" => {
+                self.files.resolve(value)?;
+            }
+            "ob" => self.current_object = Some(self.objects.resolve(value)?),
+            "cob" => {
+                self.objects.resolve(value)?;
+            }
+            "calls" => self.next_line = CostLine::Call,
+            "jump" | "jcnd" => self.next_line = CostLine::Jump,
+            "positions" => self.n_positions = value.split_ascii_whitespace().count(),
+            "events" => {
+                self.run.events = value.split_ascii_whitespace().map(str::to_string).collect();
+                self.run.metadata.events_recorded.clone_from(&self.run.events);
+                self.computed_totals = vec![0; self.run.events.len()];
+            }
+            // `cg_diff` describes its output by the files it compares.
+            "desc" if value.trim_start().starts_with("Files compared:") => {
+                self.run.kind = RunKind::Delta;
+            }
+            "desc" => {
+                if let Some(trigger) = value.trim_start().strip_prefix("Trigger:") {
+                    self.run.metadata.trigger = Some(trigger.trim().to_string());
+                }
+            }
+            "creator" => self.run.metadata.creator = Some(value.trim().to_string()),
+            "cmd" => self.run.metadata.command = Some(value.trim().to_string()),
+            "pid" => self.run.metadata.pid = value.trim().parse().ok(),
+            "part" => self.run.metadata.parts = value.trim().parse().into_iter().collect(),
+            "thread" => self.run.metadata.threads = value.trim().parse().into_iter().collect(),
+            "summary" => self.summary = Some(parse_cost_line(value, 0)?),
+            "totals" => self.totals = Some(parse_cost_line(value, 0)?),
+            // Header lines we do not make use of.
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Parse a raw `callgrind.out.<pid>` file and return a `Run` from it.
+///
+/// Both the self and the inclusive cost of each function are loaded. The inclusive cost is the
+/// self cost plus the cost of the calls, as `callgrind_annotate --inclusive=yes` computes it. The
+/// totals are taken from `totals:`, then `summary:`, then are computed if neither is present.
+/// The output of `cg_diff`, whose costs may be negative, is loaded as a [`RunKind::Delta`].
+///
+/// `file` is only used to report errors. With [`ParseOptions::lenient`], malformed lines are
+/// skipped with a warning.
+pub fn parse<R: std::io::BufRead>(
+    input: R,
+    file: &str,
+    options: &ParseOptions,
+) -> Result<Run, ParseError> {
+    let mut parser = Parser::new();
+
+    let mut n_lines = 0;
+    for (i, line) in input.lines().enumerate() {
+        n_lines = i + 1;
+        let line = line.map_err(|error| ParseError::new(file, i + 1, ParseErrorKind::Io(error)))?;
+        let line = line.trim_end();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Err(kind) = parser.parse_line(line, options) {
+            let error = ParseError::new(file, i + 1, kind);
+            if options.lenient {
+                warn_skipped(&error);
+            } else {
+                return Err(error);
+            }
+        }
+    }
+
+    let mut run = parser.run;
+    if run.events.is_empty() {
+        return Err(ParseError::new(
+            file,
+            n_lines + 1,
+            ParseErrorKind::UnexpectedEof {
+                expected: "an `events:` line",
+            },
+        ));
+    }
+    let mut totals = parser
+        .totals
+        .or(parser.summary)
+        .unwrap_or(parser.computed_totals);
+    totals.resize(run.events.len(), 0);
+    run.totals = totals;
+    run.loaded = LoadedCosts::BOTH;
+    if run.has_negative_costs() {
+        run.kind = RunKind::Delta;
+    }
+    Ok(run)
+}
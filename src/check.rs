@@ -0,0 +1,137 @@
+use std::process::ExitCode;
+
+use crate::{
+    args::{Args, Budget, RelativeTo},
+    runs::Records,
+};
+
+/// The exit code of `--check` when a cost increased beyond its budget.
+const EXIT_REGRESSION: u8 = 3;
+
+/// The exit code of `--check` when the total decreased beyond `--improvement-threshold`.
+const EXIT_IMPROVEMENT: u8 = 4;
+
+/// The outcome of `--check`: the changes of costs beyond their budgets.
+#[derive(Default)]
+pub struct CheckReport {
+    /// The costs that increased beyond their budget, described.
+    pub regressions: Vec<String>,
+    /// The totals that decreased beyond `--improvement-threshold`, described.
+    pub improvements: Vec<String>,
+}
+
+impl CheckReport {
+    /// Return the exit code telling the outcome: a regression takes precedence over an
+    /// improvement.
+    pub fn exit_code(&self) -> ExitCode {
+        if !self.regressions.is_empty() {
+            ExitCode::from(EXIT_REGRESSION)
+        } else if !self.improvements.is_empty() {
+            ExitCode::from(EXIT_IMPROVEMENT)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Return the run that a profile run is compared to, as per `--relative-to`, or `None` if it is
+/// the reference itself.
+fn reference_run(records: &Records, relative_to: RelativeTo, run: usize) -> Option<usize> {
+    let reference = match relative_to {
+        RelativeTo::First => records.profile_runs().next()?,
+        RelativeTo::Last => records.profile_runs().next_back()?,
+        RelativeTo::Previous => records.profile_runs().take_while(|&x| x < run).last()?,
+        RelativeTo::Column(x) => x as usize,
+    };
+    (reference != run).then_some(reference)
+}
+
+/// Describe the change of a cost beyond a limit: `v2: work +500 (+16.667%), budget 100`.
+fn describe(run: &str, what: &str, cost: i64, reference: i64, limit: (&str, Budget)) -> String {
+    let change = cost - reference;
+    let percentage = if reference == 0 {
+        String::new()
+    } else {
+        format!(" ({:+.3}%)", change as f64 * 100.0 / reference as f64)
+    };
+    let (limit, budget) = limit;
+    format!("{run}: {what} {change:+}{percentage}, {limit} {budget}")
+}
+
+impl Records {
+    /// Return the most that a symbol left out of the listing of a run may cost for an event: the
+    /// percentage of [`Self::runs_below_threshold`] of its total, or 0 if the listing is complete.
+    fn left_out_bound(&self, event: usize, run: usize) -> i64 {
+        let below = self.runs_below_threshold[run].unwrap_or(0.0);
+        (self.runs_totals[event][run] as f64 * below / 100.0).round() as i64
+    }
+
+    /// Check the costs of an event in each compared profile run against the budgets of `config`
+    /// (see `--check`).
+    ///
+    /// Each symbol is reported once at most, with the first budget it exceeds: those whose pattern
+    /// it matches, or else `--max-symbol-increase`. The main costs of symbols are checked (see
+    /// [`Self::main_costs`]), unless they are unknown in the checked run. A symbol left out of the
+    /// reference is checked against the most it may have cost there (see
+    /// [`Self::left_out_bound`]), so that a new hot symbol is caught. Runs that did not record the
+    /// event are not compared.
+    pub fn check(&self, config: &Args, event: usize) -> CheckReport {
+        let mut report = CheckReport::default();
+        let total_name = format!("Total {}", self.events[event]);
+        for run in self.profile_runs() {
+            let Some(reference) = reference_run(self, config.relative_to, run) else {
+                continue;
+            };
+            // The costs of an event that either run did not record cannot be compared.
+            if !(self.runs_recorded[event][run] && self.runs_recorded[event][reference]) {
+                continue;
+            }
+            let name = &self.run_names[run];
+            let (total, reference_total) =
+                (self.runs_totals[event][run], self.runs_totals[event][reference]);
+            if let Some(budget) = config.max_total_increase {
+                if budget.is_exceeded(total - reference_total, reference_total) {
+                    let limit = ("budget", budget);
+                    let regression = describe(name, &total_name, total, reference_total, limit);
+                    report.regressions.push(regression);
+                }
+            }
+            if let Some(threshold) = config.improvement_threshold {
+                if threshold.is_exceeded(reference_total - total, reference_total) {
+                    let limit = ("threshold", threshold);
+                    let improvement = describe(name, &total_name, total, reference_total, limit);
+                    report.improvements.push(improvement);
+                }
+            }
+
+            for symbol in &self.symbols {
+                let costs = &self.main_costs(symbol)[event];
+                let Some(cost) = costs[run] else {
+                    continue;
+                };
+                let (reference_cost, what) = if let Some(reference_cost) = costs[reference] {
+                    (reference_cost, symbol.display_name())
+                } else {
+                    let label = self.below_threshold_label(reference).unwrap_or_default();
+                    let bound = self.left_out_bound(event, reference);
+                    (bound, format!("{} (was {label})", symbol.display_name()))
+                };
+                let key = symbol.key();
+                let matching = config.symbol_budget.iter().filter(|x| x.regex.is_match(&key));
+                let mut budgets = matching.map(|budget| budget.budget).collect::<Vec<_>>();
+                if budgets.is_empty() {
+                    budgets.extend(config.max_symbol_increase);
+                }
+                let exceeded = budgets
+                    .into_iter()
+                    .find(|budget| budget.is_exceeded(cost - reference_cost, reference_cost));
+                if let Some(budget) = exceeded {
+                    let limit = ("budget", budget);
+                    let regression = describe(name, &what, cost, reference_cost, limit);
+                    report.regressions.push(regression);
+                }
+            }
+        }
+        report
+    }
+}
@@ -1,10 +1,104 @@
 use itertools::Itertools;
 
 use crate::args::{Args, RelativeTo, Show};
-use crate::runs::{Records, RecordsSymbol};
+use crate::check::CheckReport;
+use crate::runs::{Records, RecordsLine, RecordsSymbol, RunKind};
 
-pub fn display(config: &Args, records: &Records) {
-    Displayer::new(config, records).display();
+/// Display the costs of the given events (indices in `records.events`).
+pub fn display(config: &Args, records: &Records, events: &[usize]) {
+    Displayer::new(config, records, events).display();
+}
+
+/// Display the given lines of annotated source, with the costs of the given event.
+pub fn display_source(config: &Args, records: &Records, lines: &[&RecordsLine], event: usize) {
+    Displayer::new(config, records, &[event]).display_source(lines);
+}
+
+/// Display how each run was produced, as a block of `<field>: <value>` lines per run.
+///
+/// Several parts or threads are listed for the runs that add them up. The samples of sample
+/// groups are listed, with the statistics of their totals.
+///
+/// Fields that a run does not tell are omitted.
+pub fn display_metadata(records: &Records) {
+    for (run, (name, metadata)) in records.run_names.iter().zip(&records.runs_metadata).enumerate()
+    {
+        println!("Run {name}");
+        let pid = metadata.pid.map(|pid| pid.to_string());
+        let parts = Some(metadata.parts.iter().join(" ")).filter(|parts| !parts.is_empty());
+        let threads = Some(metadata.threads.iter().join(" ")).filter(|threads| !threads.is_empty());
+        let events = Some(metadata.events_recorded.join(" ")).filter(|events| !events.is_empty());
+        let samples = Some(metadata.samples.join(" ")).filter(|samples| !samples.is_empty());
+        let totals = records
+            .events
+            .iter()
+            .zip(&records.runs_totals_stats)
+            .filter_map(|(event, stats)| {
+                let stats = stats[run]?;
+                Some(format!(
+                    "{event} {} (min {}, max {}, std dev {:.1})",
+                    stats.rounded_mean(),
+                    stats.min,
+                    stats.max,
+                    stats.std_dev
+                ))
+            })
+            .join(", ");
+        let totals = Some(totals).filter(|totals| !totals.is_empty());
+        let fields = [
+            ("Data file", metadata.data_file.as_ref()),
+            ("Creator", metadata.creator.as_ref()),
+            ("Command", metadata.command.as_ref()),
+            ("PID", pid.as_ref()),
+            ("Parts", parts.as_ref()),
+            ("Threads", threads.as_ref()),
+            ("Events recorded", events.as_ref()),
+            ("Thresholds", metadata.thresholds.as_ref()),
+            ("Trigger", metadata.trigger.as_ref()),
+            ("Samples", samples.as_ref()),
+            ("Mean totals", totals.as_ref()),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                print!("  {field}:");
+                print_n(' ', "Events recorded".len() + 2 - field.len());
+                println!("{value}");
+            }
+        }
+    }
+    println!();
+}
+
+/// Display the symbols that may have been renamed, as lines of an `--aliases` file.
+pub fn display_rename_candidates(renames: &[(String, String)]) {
+    if renames.is_empty() {
+        println!("No possible renames");
+    } else {
+        println!("Possible renames (confirm them in a file given to --aliases):");
+        for (old, new) in renames {
+            println!("{old}=>{new}");
+        }
+    }
+    println!();
+}
+
+/// Display the outcome of `--check`: the costs that increased beyond their budget, or else the
+/// totals that decreased beyond the improvement threshold.
+pub fn display_check(report: &CheckReport) {
+    println!();
+    if !report.regressions.is_empty() {
+        println!("Check failed, {} over budget:", report.regressions.len());
+        for regression in &report.regressions {
+            println!("  {regression}");
+        }
+    } else if !report.improvements.is_empty() {
+        println!("Check passed, improved beyond the threshold (update the baseline):");
+        for improvement in &report.improvements {
+            println!("  {improvement}");
+        }
+    } else {
+        println!("Check passed");
+    }
 }
 
 /// The width of the `percent_diff` column (`+ 12.345%`).
@@ -21,41 +115,57 @@ const PERCENTDIFF_WIDTH: u32 = 9;
 /// The name of the "symbol" for the row that contains the total IR for runs.
 const TOTAL_IR_ROW_NAME: &str = "Total IR";
 
+/// The name of the "symbol" for the row that contains the totals for runs, for events other than
+/// `Ir`.
+const TOTAL_ROW_NAME: &str = "Total";
+
 /// Context for displaying a [`Records`].
 struct Displayer<'a> {
     /// The program configuration.
     config: &'a Args,
     /// The records to display.
     records: &'a Records,
+    /// The events to display (indices in `records.events`).
+    events: &'a [usize],
     /// The length of the longest symbol.
     max_symbol_width: u32,
     /// The length (in digits) of the highest `total_ir`.
     max_total_ir_width: u8,
     /// The width that a column takes in-between the ` | `.
     run_width: u32,
+    /// The width that the reference column takes in-between the ` | `.
+    ref_width: u32,
     /// The total width of a line.
     line_width: u32,
     /// The index of the reference column.
     reference_column: u32,
+    /// Whether compared columns end with a flag telling whether the difference is significant,
+    /// when sample groups are compared (see [`Records::is_significant`]).
+    significance_flags: bool,
 }
 
 impl<'a> Displayer<'a> {
     /// Create a new [`Displayer`].
-    fn new(config: &'a Args, records: &'a Records) -> Self {
+    fn new(config: &'a Args, records: &'a Records, events: &'a [usize]) -> Self {
         let mut ret = Self {
             config,
             records,
-            max_symbol_width: get_max_symbol_length(records, config.all),
-            max_total_ir_width: get_highest_total_ir_length(records),
+            events,
+            max_symbol_width: get_max_symbol_length(records, config.all, events, &config.show),
+            max_total_ir_width: get_highest_total_ir_length(records, events)
+                .max(get_max_below_threshold_length(records, config.all, events, &config.show)),
             run_width: 0,
+            ref_width: 0,
             line_width: 0,
             reference_column: 0,
+            significance_flags: records.has_sample_groups(),
         };
         ret.compute_widths();
 
+        // Delta runs are never the reference.
         ret.reference_column = match &config.relative_to {
-            RelativeTo::First => 0,
-            RelativeTo::Last => (records.n_runs() - 1) as u32,
+            RelativeTo::First => records.profile_runs().next().unwrap_or(0) as u32,
+            RelativeTo::Last => records.profile_runs().next_back().unwrap_or(0) as u32,
             RelativeTo::Previous => u32::MAX,
             RelativeTo::Column(x) => *x,
         };
@@ -77,22 +187,97 @@ impl<'a> Displayer<'a> {
         self.show_total_ir_line();
         self.show_delimitation_line();
         for symbol in &self.records.symbols {
-            if self.config.all || !symbol.irs.iter().all_equal() {
+            if self.config.all || has_changes(self.records, symbol, self.events, &self.config.show)
+            {
                 self.show_symbol_row(symbol);
             }
         }
     }
 
+    /// Display lines of annotated source on the standard output.
+    ///
+    /// The reference column shows the cost of each line, the other columns the difference with
+    /// respect to it, and delta runs their own difference. A line shows like:
+    /// ```no_compile
+    /// <line> | <ir_ref> | <ir-diff> | <source>
+    /// ```
+    fn display_source(&self, lines: &[&RecordsLine]) {
+        let event = self.events[0];
+        let line_width = lines
+            .iter()
+            .map(|line| line.line.to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max("Line".len());
+        let column_width = |i| {
+            if self.is_breakdown(i) {
+                self.breakdown_width(i, self.max_total_ir_width as usize)
+            } else if self.is_ref_column(i) {
+                self.max_total_ir_width as usize
+            } else {
+                self.max_total_ir_width as usize + 1
+            }
+        };
+
+        print_left("Line", line_width);
+        for i in 0..self.records.n_runs() {
+            print!(" | ");
+            print_centered(&self.records.run_names[i], column_width(i));
+        }
+        println!(" | Source");
+
+        let mut file = None;
+        for line in lines {
+            // Several files may match, each gets a delimitation line with its name.
+            if file != Some(&line.file) {
+                print_n('-', line_width);
+                for i in 0..self.records.n_runs() {
+                    print!("-+-");
+                    print_n('-', column_width(i));
+                }
+                println!("-+- {}", line.file);
+                file = Some(&line.file);
+            }
+
+            print_right(&line.line.to_string(), line_width);
+            let irs = line.costs[event].iter().copied().map(Some).collect::<Vec<_>>();
+            for (i, &ir) in irs.iter().enumerate() {
+                print!(" | ");
+                if self.is_delta(i) {
+                    self.show_symbol_irdff(ir, Some(0));
+                } else if self.is_breakdown(i) {
+                    print_n(' ', column_width(i) - self.max_total_ir_width as usize);
+                    self.show_symbol_ir(i, ir);
+                } else if self.is_ref_column(i) {
+                    self.show_symbol_ir(i, ir);
+                } else {
+                    self.show_symbol_irdff(ir, self.get_reference_ir_for(i, &irs));
+                }
+            }
+            println!(" | {}", line.text);
+        }
+    }
+
+    /// Return the name of a column.
+    ///
+    /// When several events are displayed, the event is appended to the name of the run.
+    fn column_name(&self, run: usize, event: usize) -> String {
+        let run_name = &self.records.run_names[run];
+        if self.events.len() == 1 {
+            run_name.clone()
+        } else {
+            format!("{run_name}:{}", self.records.events[event])
+        }
+    }
+
     /// Show the header line.
     fn show_header(&self) {
         print!("Symbol");
         print_n(' ', self.max_symbol_width as usize - "Symbol".len());
-        for (i, col_name) in self.records.run_names.iter().enumerate() {
-            print!(" | ");
-            if self.is_ref_column(i) {
-                print_centered(col_name, self.max_total_ir_width as usize);
-            } else {
-                print_centered(col_name, self.run_width as usize);
+        for &event in self.events {
+            for i in 0..self.records.n_runs() {
+                print!(" | ");
+                print_centered(&self.column_name(i, event), self.column_width(i));
             }
         }
         println!();
@@ -101,12 +286,10 @@ impl<'a> Displayer<'a> {
     /// Show a `---+----+---` line as a horizontal separation.
     fn show_delimitation_line(&self) {
         print_n('-', self.max_symbol_width as usize);
-        for i in 0..self.records.run_names.len() {
-            print!("-+-");
-            if self.is_ref_column(i) {
-                print_n('-', self.max_total_ir_width as usize);
-            } else {
-                print_n('-', self.run_width as usize);
+        for _ in self.events {
+            for i in 0..self.records.n_runs() {
+                print!("-+-");
+                print_n('-', self.column_width(i));
             }
         }
         println!();
@@ -114,15 +297,27 @@ impl<'a> Displayer<'a> {
 
     /// Show the "Total IR" line.
     fn show_total_ir_line(&self) {
-        print_left(TOTAL_IR_ROW_NAME, self.max_symbol_width as usize);
-        for (i, ir) in self.records.runs_total_irs.iter().enumerate() {
-            let s = ir.to_string();
-            print!(" | ");
-            if self.is_ref_column(i) {
-                print_right(&s, self.max_total_ir_width as usize);
-            } else {
-                let reference_ir = self.get_reference_total_ir_for(i);
-                self.show_run_details(*ir, reference_ir);
+        print_left(total_row_name(self.records, self.events), self.max_symbol_width as usize);
+        for &event in self.events {
+            // The inclusive cost of the whole program is its total cost.
+            let totals = self.records.known_totals(event);
+            for i in 0..totals.len() {
+                print!(" | ");
+                if self.is_delta(i) {
+                    self.show_delta(i, totals[i], totals[i]);
+                } else if self.is_breakdown(i) {
+                    self.show_breakdown(i, totals[i], totals[i]);
+                } else if self.is_ref_column(i) {
+                    self.show_reference(i, totals[i], totals[i]);
+                } else {
+                    let significant = self.records.is_total_significant(
+                        event,
+                        i,
+                        self.reference_for(i),
+                        self.config.significance,
+                    );
+                    self.show_run_details(i, &totals, &totals, significant);
+                }
             }
         }
         println!();
@@ -130,44 +325,130 @@ impl<'a> Displayer<'a> {
 
     /// Display the row with details for a single symbol.
     fn show_symbol_row(&self, symbol: &RecordsSymbol) {
-        print_left(&symbol.name, self.max_symbol_width as usize);
-        for (i, ir) in symbol.irs.iter().enumerate() {
-            print!(" | ");
-            if self.is_ref_column(i) {
-                // If it's the reference column, just print the IR count.
-                self.show_symbol_ir(*ir);
-            } else {
-                let reference_ir = self.get_reference_ir_for(i, symbol);
-                self.show_run_details(*ir, reference_ir);
+        print_left(&symbol.display_name(), self.max_symbol_width as usize);
+        for &event in self.events {
+            let irs = &symbol.costs[event];
+            let inclusive_irs = &symbol.inclusive[event];
+            for i in 0..irs.len() {
+                print!(" | ");
+                if self.is_delta(i) {
+                    self.show_delta(i, irs[i], inclusive_irs[i]);
+                } else if self.is_breakdown(i) {
+                    self.show_breakdown(i, irs[i], inclusive_irs[i]);
+                } else if self.is_ref_column(i) {
+                    // If it's the reference column, just print the IR count.
+                    self.show_reference(i, irs[i], inclusive_irs[i]);
+                } else {
+                    let significant = self.records.is_significant(
+                        symbol,
+                        event,
+                        i,
+                        self.reference_for(i),
+                        self.config.significance,
+                    );
+                    self.show_run_details(i, irs, inclusive_irs, significant);
+                }
             }
         }
         println!();
     }
 
-    /// Display the columns (as per `--show`) with the given details.
-    fn show_run_details(&self, ir: u64, reference_ir: u64) {
-        for (i, x) in self.config.show.iter().enumerate() {
-            if i != 0 {
+    /// Display the IR count of the reference column: the self count, the inclusive count, or
+    /// both, depending on which ones `--show` uses.
+    fn show_reference(&self, i: usize, ir: Option<i64>, inclusive_ir: Option<i64>) {
+        let (self_cost, inclusive) = shown_costs(&self.config.show);
+        if self_cost {
+            self.show_symbol_ir(i, ir);
+        }
+        if inclusive {
+            if self_cost {
+                print!(" ");
+            }
+            self.show_symbol_ir(i, inclusive_ir);
+        }
+    }
+
+    /// Display the costs of a breakdown run as for the reference column, aligned to the right of
+    /// its column.
+    fn show_breakdown(&self, i: usize, ir: Option<i64>, inclusive_ir: Option<i64>) {
+        print_n(' ', self.column_width(i) - self.ref_width as usize);
+        self.show_reference(i, ir, inclusive_ir);
+    }
+
+    /// Display the difference held by a delta run: the self one, or the inclusive one if `--show`
+    /// uses inclusive costs only.
+    fn show_delta(&self, i: usize, ir: Option<i64>, inclusive_ir: Option<i64>) {
+        let ir = match shown_costs(&self.config.show) {
+            (false, true) => inclusive_ir,
+            _ => ir,
+        };
+        match ir {
+            Some(_) => self.show_symbol_irdff(ir, Some(0)),
+            None => self.show_symbol_ir(i, ir),
+        }
+    }
+
+    /// Display the columns (as per `--show`) for the `i`-th run, among the self and inclusive
+    /// costs of all runs, then whether the difference is significant if sample groups are
+    /// compared: `*` if it is, `~` if it is noise, nothing if it was not tested.
+    fn show_run_details(
+        &self,
+        i: usize,
+        irs: &[Option<i64>],
+        inclusive_irs: &[Option<i64>],
+        significant: Option<bool>,
+    ) {
+        let (ir, reference_ir) = (irs[i], self.get_reference_ir_for(i, irs));
+        let (inclusive_ir, inclusive_reference_ir) =
+            (inclusive_irs[i], self.get_reference_ir_for(i, inclusive_irs));
+        for (j, x) in self.config.show.iter().enumerate() {
+            if j != 0 {
                 // Print a space between that value and the previous one.
                 print!(" ");
             }
             match x {
-                Show::IRCount => self.show_symbol_ir(ir),
+                Show::IRCount => self.show_symbol_ir(i, ir),
                 Show::PercentageDiff => self.show_symbol_percentdff(ir, reference_ir),
                 Show::IRCountDiff => self.show_symbol_irdff(ir, reference_ir),
+                Show::InclIRCount => self.show_symbol_ir(i, inclusive_ir),
+                Show::InclPercentageDiff => {
+                    self.show_symbol_percentdff(inclusive_ir, inclusive_reference_ir);
+                }
+                Show::InclIRCountDiff => {
+                    self.show_symbol_irdff(inclusive_ir, inclusive_reference_ir);
+                }
                 Show::All => unreachable!(),
             }
         }
+        if self.significance_flags {
+            let flag = match significant {
+                Some(true) => '*',
+                Some(false) => '~',
+                None => ' ',
+            };
+            print!(" {flag}");
+        }
     }
 
-    /// Display the IR count, correctly aligned.
-    fn show_symbol_ir(&self, ir: u64) {
-        let s = ir.to_string();
+    /// Display the IR count of the `i`-th run, correctly aligned.
+    ///
+    /// An unknown count shows as the threshold of the run it is below (`<1%`).
+    fn show_symbol_ir(&self, i: usize, ir: Option<i64>) {
+        let s = match ir {
+            Some(ir) => ir.to_string(),
+            None => self.records.below_threshold_label(i).unwrap_or_default(),
+        };
         print_right(&s, self.max_total_ir_width as usize);
     }
 
     /// Display the IR difference, correctly aligned.
-    fn show_symbol_irdff(&self, ir: u64, reference_ir: u64) {
+    ///
+    /// The difference is unknown (`?`) if either count is.
+    fn show_symbol_irdff(&self, ir: Option<i64>, reference_ir: Option<i64>) {
+        let (Some(ir), Some(reference_ir)) = (ir, reference_ir) else {
+            print_right("?", (self.max_total_ir_width + 1) as usize);
+            return;
+        };
         let diff = ir.abs_diff(reference_ir);
         if diff == 0 {
             print_right("-", (self.max_total_ir_width + 1) as usize);
@@ -189,8 +470,13 @@ impl<'a> Displayer<'a> {
     }
 
     /// Display the IR percentage difference, correctly aligned.
-    #[allow(clippy::unused_self)]
-    fn show_symbol_percentdff(&self, ir: u64, reference_ir: u64) {
+    ///
+    /// The difference is unknown (`?`) if either count is.
+    fn show_symbol_percentdff(&self, ir: Option<i64>, reference_ir: Option<i64>) {
+        let (Some(ir), Some(reference_ir)) = (ir, reference_ir) else {
+            print_right("? ", PERCENTDIFF_WIDTH as usize);
+            return;
+        };
         let diff = ir.abs_diff(reference_ir);
         let percent = if reference_ir == 0 {
             100.0
@@ -233,92 +519,226 @@ impl<'a> Displayer<'a> {
     ///                    ^^^^^^^^^^^^^^^^^^^^^ Repeated for each column other than the ref
     /// ```
     ///
-    /// The `<ir>`, `<ir-diff>` and `<%>` fields will show only if they are selected via `--show`.
+    /// The `<ir>`, `<ir-diff>` and `<%>` fields will show only if they are selected via `--show`,
+    /// and so will their inclusive counterparts. The reference column shows `<ir_ref>` for self
+    /// costs, inclusive costs, or both, and so do breakdown runs. Delta runs show a single
+    /// `<ir-diff>`.
     fn compute_widths(&mut self) {
         let ir_len = self.max_total_ir_width as u32;
 
-        let ir_ref = ir_len;
-        let ir = if self.config.show.contains(&Show::IRCount) {
-            ir_len
-        } else {
-            0
-        };
-        let ir_diff = if self.config.show.contains(&Show::IRCountDiff) {
-            ir_len + 1 // Account for the `+` or `-` sign.
-        } else {
-            0
-        };
-        let percent_diff = if self.config.show.contains(&Show::PercentageDiff) {
-            PERCENTDIFF_WIDTH
-        } else {
-            0
+        let ir_ref = match shown_costs(&self.config.show) {
+            (true, true) => 2 * ir_len + 1,
+            _ => ir_len,
         };
-
+        let ir = self
+            .config
+            .show
+            .iter()
+            .filter(|show| matches!(show, Show::IRCount | Show::InclIRCount))
+            .count() as u32
+            * ir_len;
+        let ir_diff = self
+            .config
+            .show
+            .iter()
+            .filter(|show| matches!(show, Show::IRCountDiff | Show::InclIRCountDiff))
+            .count() as u32
+            * (ir_len + 1); // Account for the `+` or `-` sign.
+        let percent_diff = self
+            .config
+            .show
+            .iter()
+            .filter(|show| matches!(show, Show::PercentageDiff | Show::InclPercentageDiff))
+            .count() as u32
+            * PERCENTDIFF_WIDTH;
+
+        self.ref_width = ir_ref;
         self.run_width = ir + // <ir>
              ir_diff +        // <ir-diff>
              percent_diff +   // <%>
-             ((self.config.show.len() - 1) as u32); // spaces
+             ((self.config.show.len() - 1) as u32) + // spaces
+             if self.significance_flags { 2 } else { 0 }; // ` *`
 
         self.line_width = self.max_symbol_width + // <symbol>
-            3 +                 // ` | `
-            ir_ref +            // <ir_ref>
-            (3 +                // ` | `
-             self.run_width) *  // <ir> <ir-diff> <%>
-            ((self.records.n_runs() - 1) as u32); // For each column other than the reference one.
+            (0..self.records.n_runs())
+                .map(|i| 3 + self.column_width(i) as u32) // ` | ` and the column
+                .sum::<u32>() *
+            (self.events.len() as u32); // For each event.
     }
 
-    /// Return whether the column at index `i` is the reference column.
+    /// Return the width that the column of the `i`-th run takes in-between the ` | `.
+    fn column_width(&self, i: usize) -> usize {
+        if self.is_delta(i) {
+            self.max_total_ir_width as usize + 1 // Account for the `+` or `-` sign.
+        } else if self.is_breakdown(i) {
+            self.breakdown_width(i, self.ref_width as usize)
+        } else if self.is_ref_column(i) {
+            self.ref_width as usize
+        } else {
+            self.run_width as usize
+        }
+    }
+
+    /// Return whether the `i`-th run is a delta run, shown as is rather than compared.
+    fn is_delta(&self, i: usize) -> bool {
+        self.records.runs_kind[i] == RunKind::Delta
+    }
+
+    /// Return the width of the column of the `i`-th run, a breakdown run whose costs take `width`.
     ///
-    /// If the relative is set to previous, the reference column is considered to be the first.
-    fn is_ref_column(&self, i: usize) -> bool {
-        (i as u32) == self.reference_column || (i == 0 && self.reference_column == u32::MAX)
+    /// The column is widened to fit its name, which is short (`pid 1234`).
+    fn breakdown_width(&self, i: usize, width: usize) -> usize {
+        let name_width = self.events.iter().map(|&event| self.column_name(i, event).len()).max();
+        width.max(name_width.unwrap_or(0))
     }
 
-    /// Get the reference IR count for the given symbol and run.
-    fn get_reference_ir_for(&self, i: usize, symbol: &RecordsSymbol) -> u64 {
+    /// Return whether the `i`-th run is a breakdown run, shown as is rather than compared.
+    fn is_breakdown(&self, i: usize) -> bool {
+        self.records.runs_kind[i] == RunKind::Breakdown
+    }
+
+    /// Return whether the column at index `i` is the reference column.
+    ///
+    /// If the relative is set to previous, the reference column is considered to be the first
+    /// profile run.
+    fn is_ref_column(&self, i: usize) -> bool {
         if self.reference_column == u32::MAX {
-            symbol.irs[i - 1]
+            self.records.profile_runs().next() == Some(i)
         } else {
-            symbol.irs[self.reference_column as usize]
+            (i as u32) == self.reference_column && !self.is_delta(i)
         }
     }
 
-    /// Get the reference total IR count for the given run.
-    fn get_reference_total_ir_for(&self, i: usize) -> u64 {
+    /// Get the index of the reference run of the given run.
+    ///
+    /// If the relative is set to previous, this is the previous profile run, skipping delta runs.
+    fn reference_for(&self, i: usize) -> usize {
         if self.reference_column == u32::MAX {
-            self.records.runs_total_irs[i - 1]
+            let previous = self.records.profile_runs().take_while(|&run| run < i).last();
+            previous.expect("The first profile run is the reference")
         } else {
-            self.records.runs_total_irs[self.reference_column as usize]
+            self.reference_column as usize
         }
     }
+
+    /// Get the reference IR count for the given run, among the costs of all runs.
+    fn get_reference_ir_for(&self, i: usize, irs: &[Option<i64>]) -> Option<i64> {
+        irs[self.reference_for(i)]
+    }
+}
+
+/// Return whether self costs and inclusive costs are shown, as per `--show`.
+fn shown_costs(show: &[Show]) -> (bool, bool) {
+    (
+        show.iter().any(|show| !show.is_inclusive()),
+        show.iter().any(|show| show.is_inclusive()),
+    )
+}
+
+/// Return whether the cost of any of the given events is not the same throughout all profile
+/// runs, or is not zero in a delta run.
+///
+/// Only the costs that are shown (self, inclusive or both) are taken into account. An unknown
+/// cost (below the threshold of its run) may differ from any known one.
+fn has_changes(records: &Records, symbol: &RecordsSymbol, events: &[usize], show: &[Show]) -> bool {
+    let changed = |costs: &[Option<i64>]| {
+        !records.profile_runs().map(|i| costs[i]).all_equal()
+            || costs
+                .iter()
+                .zip(&records.runs_kind)
+                .any(|(&cost, &kind)| kind == RunKind::Delta && cost.is_some_and(|cost| cost != 0))
+    };
+    let (self_cost, inclusive) = shown_costs(show);
+    events.iter().any(|&event| {
+        (self_cost && changed(&symbol.costs[event]))
+            || (inclusive && changed(&symbol.inclusive[event]))
+    })
+}
+
+/// Return the name of the totals row.
+fn total_row_name(records: &Records, events: &[usize]) -> &'static str {
+    if events.len() == 1 && records.events[events[0]] == "Ir" {
+        TOTAL_IR_ROW_NAME
+    } else {
+        TOTAL_ROW_NAME
+    }
 }
 
 /// Get the length of the longest symbol.
 ///
 /// If `display_all` (the `-a` option) is disabled, this will only take into account symbols for
-/// which the IR count is not the same throughout all runs.
+/// which the cost of one of the `events` is not the same throughout all runs.
 ///
 /// If there is no symbol to display, this returns 0.
-fn get_max_symbol_length(records: &Records, display_all: bool) -> u32 {
-    const TOTAL_IR_LEN: u32 = TOTAL_IR_ROW_NAME.len() as u32;
+fn get_max_symbol_length(
+    records: &Records,
+    display_all: bool,
+    events: &[usize],
+    show: &[Show],
+) -> u32 {
+    // The header must fit as well, and "Total" is shorter than "Symbol".
+    let total_len = total_row_name(records, events).len().max("Symbol".len()) as u32;
 
     (records
         .symbols
         .iter()
-        .filter(|record| display_all || !record.irs.iter().all_equal())
-        .map(|record| record.name.len())
+        .filter(|record| display_all || has_changes(records, record, events, show))
+        .map(|record| record.display_name().len())
         .max()
         .unwrap_or(0) as u32)
-        .max(TOTAL_IR_LEN)
+        .max(total_len)
 }
 
-/// Get the length in digits of the highest `total_ir`.
-fn get_highest_total_ir_length(records: &Records) -> u8 {
-    records
-        .runs_total_irs
+/// Get the length in digits of the highest total among the given events.
+///
+/// The differences held by delta runs do not add up to their total, so their symbols are taken
+/// into account as well.
+fn get_highest_total_ir_length(records: &Records, events: &[usize]) -> u8 {
+    let delta_costs = events.iter().flat_map(|&event| {
+        records.symbols.iter().flat_map(move |symbol| {
+            let costs = symbol.costs[event].iter().chain(&symbol.inclusive[event]);
+            costs
+                .zip(records.runs_kind.iter().cycle())
+                .filter(|(_, &kind)| kind == RunKind::Delta)
+                .filter_map(|(&cost, _)| cost)
+        })
+    });
+    events
         .iter()
+        .flat_map(|&event| records.runs_totals[event].iter().copied())
+        .chain(delta_costs)
+        .map(i64::unsigned_abs)
+        .max()
+        .map_or(1, |x| (x.checked_ilog10().unwrap_or(0) + 1) as u8)
+}
+
+/// Get the length of the longest label of an unknown cost (`<1%`) among the given events, or 0 if
+/// there is none.
+///
+/// As for [`get_max_symbol_length`], only the symbols to display are taken into account.
+fn get_max_below_threshold_length(
+    records: &Records,
+    display_all: bool,
+    events: &[usize],
+    show: &[Show],
+) -> u8 {
+    let has_unknown_costs = |run: usize| {
+        let mut symbols = records
+            .symbols
+            .iter()
+            .filter(|symbol| display_all || has_changes(records, symbol, events, show));
+        symbols.any(|symbol| {
+            events.iter().any(|&event| {
+                symbol.costs[event][run].is_none() || symbol.inclusive[event][run].is_none()
+            })
+        })
+    };
+    (0..records.n_runs())
+        .filter(|&run| has_unknown_costs(run))
+        .filter_map(|run| records.below_threshold_label(run))
+        .map(|label| label.len() as u8)
         .max()
-        .map_or(1, |x| (x.ilog10() + 1) as u8)
+        .unwrap_or(0)
 }
 
 /// Print the string aligned to the right within the given width.
@@ -0,0 +1,551 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    io::{BufRead, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+
+use crate::args::{InputOrder, InputSpec};
+
+/// The path that stands for the standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// The format of an input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// A CSV file, possibly holding several runs.
+    Csv,
+    /// A JSON or NDJSON export of records, possibly holding several runs.
+    Json,
+    /// The output of `callgrind_annotate`, or of `cg_annotate` before Valgrind 3.22.
+    CallgrindAnnotate,
+    /// A raw `callgrind.out.<pid>` file, as written by valgrind.
+    CallgrindOut,
+    /// The output of `cg_annotate`, from Valgrind 3.22 onwards.
+    CgAnnotate,
+    /// A raw `cachegrind.out.<pid>` file, as written by valgrind.
+    CachegrindOut,
+}
+
+impl InputKind {
+    /// Detect the kind of an input by examining its (decompressed) content rather than extension.
+    pub fn detect(input: &Input) -> Self {
+        let first_lines = input.first_lines(2);
+        let Some(first_line) = first_lines.first() else {
+            return Self::CallgrindAnnotate; // Empty file
+        };
+
+        let line = first_line.trim();
+
+        // Raw callgrind files start with an optional format comment, then `version:` or `creator:`.
+        if line.starts_with("# callgrind format")
+            || line.starts_with("version:")
+            || line.starts_with("creator: callgrind")
+        {
+            return Self::CallgrindOut;
+        }
+
+        // Raw cachegrind files start with the description of the caches, or the command.
+        if line.starts_with("# cachegrind format")
+            || line.starts_with("desc:")
+            || line.starts_with("cmd:")
+        {
+            return Self::CachegrindOut;
+        }
+
+        // `cg_annotate` starts with its `-- Metadata` section.
+        let second_line = first_lines.get(1).map_or("", |line| line.trim());
+        if line.starts_with("---") && second_line == "-- Metadata" {
+            return Self::CgAnnotate;
+        }
+
+        // JSON exports are objects, starting with `{` on their first line.
+        if line.starts_with('{') {
+            return Self::Json;
+        }
+
+        // Check if first line looks like CSV (contains commas and no typical callgrind markers)
+        if line.contains(',')
+            && !line.contains("Profile data file")
+            && !line.contains("Profiled target")
+            && !line.contains("Events recorded")
+            && !line.starts_with("Ir")
+            && !line.starts_with("---")
+        {
+            return Self::Csv;
+        }
+
+        Self::CallgrindAnnotate
+    }
+
+    /// Whether inputs of this kind hold records of several runs, with names of their own, rather
+    /// than a single run.
+    pub fn holds_runs(self) -> bool {
+        matches!(self, Self::Csv | Self::Json)
+    }
+}
+
+impl FromStr for InputKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "annotate" => Ok(Self::CallgrindAnnotate),
+            "raw" => Ok(Self::CallgrindOut),
+            "cg-annotate" => Ok(Self::CgAnnotate),
+            "cg-raw" => Ok(Self::CachegrindOut),
+            _ => bail!(
+                "Invalid input kind. Accepted values are: csv, json, annotate, raw, cg-annotate, \
+                cg-raw"
+            ),
+        }
+    }
+}
+
+impl Display for InputKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// The compression formats that inputs are decompressed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// Detect the compression of the content from its magic bytes.
+    fn detect(content: &[u8]) -> Option<Self> {
+        if content.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if content.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else if content.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::Xz)
+        } else {
+            None
+        }
+    }
+
+    /// The extensions that files compressed with this format usually have.
+    fn extensions() -> &'static [&'static str] {
+        &["gz", "zst", "xz"]
+    }
+
+    /// Return the file name without its compression extension, if it has one.
+    fn strip_extension(name: &str) -> &str {
+        Self::extensions()
+            .iter()
+            .find_map(|extension| name.strip_suffix(&format!(".{extension}")))
+            .unwrap_or(name)
+    }
+
+    /// Decompress the whole content.
+    fn decompress(self, content: &[u8]) -> Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            Self::Gzip => {
+                flate2::read::MultiGzDecoder::new(content).read_to_end(&mut decompressed)?;
+            }
+            Self::Zstd => {
+                ruzstd::decoding::StreamingDecoder::new(content)?.read_to_end(&mut decompressed)?;
+            }
+            Self::Xz => lzma_rs::xz_decompress(&mut { content }, &mut decompressed)?,
+        }
+        Ok(decompressed)
+    }
+}
+
+/// An input file, read whole and decompressed.
+///
+/// Inputs are read once, so that the standard input can be both examined to detect its format
+/// and parsed.
+pub struct Input {
+    /// The path of the input, or [`STDIN_PATH`]. Used to report errors.
+    pub path: String,
+    /// The content of the input, decompressed.
+    pub content: Vec<u8>,
+}
+
+impl Input {
+    /// Read the input at `path`, or the standard input if `path` is [`STDIN_PATH`].
+    ///
+    /// Gzip, zstd and xz inputs are decompressed, whatever their extension.
+    pub fn read(path: &str) -> Result<Self> {
+        let content = if path == STDIN_PATH {
+            let mut content = Vec::new();
+            std::io::stdin()
+                .lock()
+                .read_to_end(&mut content)
+                .context("Failed to read the standard input")?;
+            content
+        } else {
+            std::fs::read(path).with_context(|| format!("Failed to open {path}"))?
+        };
+
+        let content = match Compression::detect(&content) {
+            Some(compression) => compression
+                .decompress(&content)
+                .with_context(|| format!("Failed to decompress {path} ({compression:?})"))?,
+            None => content,
+        };
+        Ok(Self {
+            path: path.to_string(),
+            content,
+        })
+    }
+
+    /// Return a reader over the content.
+    pub fn reader(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Return the first `n` lines of the content, or fewer if it has fewer lines.
+    ///
+    /// Lines are returned without their end of line. Invalid UTF-8 is replaced.
+    pub fn first_lines(&self, n: usize) -> Vec<String> {
+        BufRead::split(self.reader(), b'\n')
+            .take(n)
+            .map_while(Result::ok)
+            .map(|line| String::from_utf8_lossy(&line).trim_end().to_string())
+            .collect()
+    }
+
+    /// Return the PID of the profiled process, if known.
+    ///
+    /// It is read from the `pid:` line of raw callgrind files, the `Profiled target:` line of
+    /// `callgrind_annotate` files (`(PID 1234, part 1)`), or else from the end of the file name
+    /// (`cachegrind.out.1234`).
+    pub fn pid(&self) -> Option<u64> {
+        let recorded = self.first_lines(32).into_iter().find_map(|line| {
+            let pid = match line.strip_prefix("pid:") {
+                Some(pid) => pid,
+                None => line.split_once("(PID ")?.1,
+            };
+            pid.trim_start()
+                .split(|c: char| !c.is_ascii_digit())
+                .next()?
+                .parse()
+                .ok()
+        });
+        recorded.or_else(|| {
+            let name = Path::new(&self.path).file_name()?.to_str()?;
+            Compression::strip_extension(name)
+                .rsplit_once('.')?
+                .1
+                .parse()
+                .ok()
+        })
+    }
+
+    /// Return the value of a field of the header, if any: what follows `<key>:` on a line, such
+    /// as `Timerange: Basic block 0 - 1200` or `cmd: ./program`.
+    ///
+    /// The `desc:` prefix of the fields of raw files is ignored (`desc: Trigger: ...`).
+    pub fn header_field(&self, key: &str) -> Option<String> {
+        self.first_lines(32).into_iter().find_map(|line| {
+            let line = line.strip_prefix("desc:").map_or(line.as_str(), str::trim_start);
+            let value = line.strip_prefix(key)?.strip_prefix(':')?;
+            Some(value.trim().to_string())
+        })
+    }
+
+    /// Return the name that a run loaded from this input gets by default, see
+    /// [`default_run_name`].
+    pub fn default_run_name(&self) -> String {
+        default_run_name(&self.path)
+    }
+}
+
+/// Return the name that a run loaded from the input at `path` gets by default: the name of the
+/// file, without its extension nor compression extension.
+fn default_run_name(path: &str) -> String {
+    if path == STDIN_PATH {
+        return "stdin".to_string();
+    }
+    let mut file = Path::new(path);
+    if file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| Compression::extensions().contains(&extension))
+    {
+        file = file.file_stem().map_or(file, Path::new);
+    }
+    file.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// An input that has been read, along with its kind and the name of its run.
+pub struct ResolvedInput {
+    pub input: Input,
+    pub kind: InputKind,
+    /// The name of the run loaded from the input.
+    ///
+    /// This is always set for inputs holding a single run. CSV and JSON inputs have their own
+    /// names and are only named if named explicitly.
+    pub name: Option<String>,
+    /// The index of the spec the input comes from, shared by the files of a directory or glob.
+    pub spec: usize,
+}
+
+/// Read the inputs and resolve their kind and the name of their run.
+///
+/// Directories and glob patterns are expanded into the files they hold or match, in `order`. The
+/// kind of an input is the one given in its spec, or is detected from its content. Names are
+/// those given in the specs, then those of `csv_names` in order for the inputs other than CSV and
+/// JSON, then the part of the file names that differs within a directory or pattern, or
+/// [`Input::default_run_name`]. When given, `csv_names` must name exactly the inputs other than
+/// CSV and JSON that are not named in their spec.
+pub fn resolve_inputs(
+    specs: &[InputSpec],
+    csv_names: &[String],
+    order: &InputOrder,
+) -> Result<Vec<ResolvedInput>> {
+    // The inputs, along with their default name.
+    let mut inputs = Vec::new();
+    for (i, spec) in specs.iter().enumerate() {
+        let resolve = |input: Input| {
+            let kind = spec.kind.unwrap_or_else(|| InputKind::detect(&input));
+            ResolvedInput {
+                input,
+                kind,
+                name: spec.name.clone(),
+                spec: i,
+            }
+        };
+        if let Some(paths) = expand(&spec.path)? {
+            if let Some(name) = &spec.name {
+                bail!(
+                    "{} stands for several files and cannot be named {name}",
+                    spec.path
+                );
+            }
+            let mut group = paths
+                .iter()
+                .map(|path| Ok(resolve(Input::read(&path.to_string_lossy())?)))
+                .collect::<Result<Vec<_>>>()?;
+            sort_inputs(&mut group, order)?;
+            let paths = group
+                .iter()
+                .map(|input| input.input.path.as_str())
+                .collect_vec();
+            let names = distinctive_names(&paths);
+            inputs.extend(group.into_iter().zip(names));
+        } else {
+            let input = resolve(Input::read(&spec.path)?);
+            let default_name = input.input.default_run_name();
+            inputs.push((input, default_name));
+        }
+    }
+
+    let mut unnamed = inputs
+        .iter_mut()
+        .filter(|(input, _)| !input.kind.holds_runs() && input.name.is_none())
+        .collect::<Vec<_>>();
+    if !csv_names.is_empty() && unnamed.len() != csv_names.len() {
+        bail!(
+            "Mismatch between `csv-names` count {} and number of unnamed non-CSV inputs {}",
+            csv_names.len(),
+            unnamed.len()
+        );
+    }
+    for (i, (input, default_name)) in unnamed.iter_mut().enumerate() {
+        input.name = Some(
+            csv_names
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| default_name.clone()),
+        );
+    }
+    Ok(inputs.into_iter().map(|(input, _)| input).collect())
+}
+
+/// Return the files that the path stands for if it is a directory or a glob pattern, or `None`
+/// if it is a single input.
+///
+/// Hidden files and subdirectories of a directory are skipped.
+fn expand(path: &str) -> Result<Option<Vec<PathBuf>>> {
+    if path == STDIN_PATH {
+        return Ok(None);
+    }
+    let files = if Path::new(path).is_dir() {
+        std::fs::read_dir(path)
+            .with_context(|| format!("Failed to list {path}"))?
+            .map(|entry| Ok(entry?.path()))
+            .filter_ok(|file| {
+                file.is_file()
+                    && !file
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else if path.contains(['*', '?', '[']) && !Path::new(path).exists() {
+        glob::glob(path)
+            .with_context(|| format!("Invalid glob pattern {path}"))?
+            .filter_ok(|file| file.is_file())
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        return Ok(None);
+    };
+    if files.is_empty() {
+        bail!("No input file in {path}");
+    }
+    Ok(Some(files))
+}
+
+/// Sort the inputs expanded from a directory or a glob pattern.
+fn sort_inputs(inputs: &mut [ResolvedInput], order: &InputOrder) -> Result<()> {
+    let by_name = |a: &ResolvedInput, b: &ResolvedInput| natural_cmp(&a.input.path, &b.input.path);
+    match order {
+        InputOrder::Natural => inputs.sort_by(by_name),
+        InputOrder::Mtime => {
+            let mut mtimes = HashMap::new();
+            for input in inputs.iter() {
+                let path = &input.input.path;
+                let mtime = std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .with_context(|| format!("Failed to get the modification time of {path}"))?;
+                mtimes.insert(path.clone(), mtime);
+            }
+            inputs.sort_by(|a, b| {
+                mtimes[&a.input.path]
+                    .cmp(&mtimes[&b.input.path])
+                    .then_with(|| by_name(a, b))
+            });
+        }
+        InputOrder::Pid => {
+            // Files without a PID come last.
+            inputs.sort_by_cached_key(|input| input.input.pid().map_or((1, 0), |pid| (0, pid)));
+        }
+        InputOrder::Field(key) => {
+            // Files without the field come last.
+            let values = inputs
+                .iter()
+                .map(|input| (input.input.path.clone(), input.input.header_field(key)))
+                .collect::<HashMap<_, _>>();
+            inputs.sort_by(|a, b| {
+                match (&values[&a.input.path], &values[&b.input.path]) {
+                    (Some(x), Some(y)) => natural_cmp(x, y),
+                    (x, y) => y.is_some().cmp(&x.is_some()),
+                }
+                .then_with(|| by_name(a, b))
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Compare two strings, with sequences of digits compared by their value.
+///
+/// This sorts `run2` before `run10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = a
+                    .peeking_take_while(char::is_ascii_digit)
+                    .collect::<String>();
+                let y = b
+                    .peeking_take_while(char::is_ascii_digit)
+                    .collect::<String>();
+                // Leading zeros do not change the value, but tell apart `01` from `1`.
+                let (x_trimmed, y_trimmed) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Return the names of the runs of files that were expanded from a directory or a glob pattern:
+/// their file names, without the prefix and suffix that they all share.
+///
+/// The prefix and suffix removed end and start at a `.` or `_`, so that names are not cut in the
+/// middle of a word or number (`callgrind.out.4242` and `callgrind.out.4343` are named `4242` and
+/// `4343`). Compression extensions are ignored. A single file is named as per
+/// [`Input::default_run_name`].
+fn distinctive_names(paths: &[&str]) -> Vec<String> {
+    let names = paths
+        .iter()
+        .map(|path| {
+            let name = Path::new(path).file_name().map_or_else(
+                || path.to_string(),
+                |name| name.to_string_lossy().to_string(),
+            );
+            Compression::strip_extension(&name).to_string()
+        })
+        .collect_vec();
+    let is_separator = |c: char| matches!(c, '.' | '_');
+
+    let first = &names[0];
+    let shortest = names.iter().map(String::len).min().unwrap_or(0);
+    // The length of the common prefix or suffix, in bytes. Only lengths that fall between the
+    // characters of the names are compared.
+    let common = |forward: bool| {
+        (0..=shortest)
+            .filter(|&len| first.is_char_boundary(if forward { len } else { first.len() - len }))
+            .take_while(|&len| {
+                names
+                    .iter()
+                    .map(|name| {
+                        if forward {
+                            name.get(..len)
+                        } else {
+                            name.get(name.len() - len..)
+                        }
+                    })
+                    .all_equal_value()
+                    .is_ok_and(|part| part.is_some())
+            })
+            .last()
+            .unwrap_or(0)
+    };
+    let prefix = first[..common(true)]
+        .rfind(is_separator)
+        .map_or(0, |i| i + 1);
+    let suffix = first[first.len() - common(false)..]
+        .find(is_separator)
+        .map_or(0, |i| common(false) - i);
+
+    names
+        .iter()
+        .zip(paths)
+        .map(|(name, path)| match name.get(prefix..name.len() - suffix) {
+            Some(distinctive) if paths.len() > 1 && !distinctive.is_empty() => {
+                distinctive.to_string()
+            }
+            _ => default_run_name(path),
+        })
+        .collect()
+}
@@ -0,0 +1,314 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    args::{JsonFormat, ParseOptions},
+    callgrind::{warn_skipped, ParseError, ParseErrorKind},
+    input::Input,
+    runs::{LoadedCosts, Records, RecordsSymbol, RunKind, RunMetadata},
+    stats::SampleStats,
+};
+
+/// The value of the `format` field of our JSON exports.
+const JSON_FORMAT: &str = "callgrind-compare";
+
+/// The version of the schema of our JSON exports, bumped on each incompatible change.
+const JSON_VERSION: u32 = 1;
+
+/// The header of a JSON export: its schema, events and runs.
+///
+/// In a JSON document, the header also holds the symbols. In NDJSON, the header is the first line,
+/// without symbols, and each following line is a [`JsonSymbol`].
+#[derive(Serialize, Deserialize)]
+struct JsonHeader {
+    /// Always [`JSON_FORMAT`].
+    format: String,
+    /// The version of the schema, at most [`JSON_VERSION`].
+    version: u32,
+    /// The events the costs are given for, in order.
+    events: Vec<String>,
+    /// The runs the costs are given for, in order.
+    runs: Vec<JsonRun>,
+    /// The symbols, in a JSON document only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    symbols: Option<Vec<JsonSymbol>>,
+}
+
+/// A run of a JSON export.
+#[derive(Serialize, Deserialize)]
+struct JsonRun {
+    name: String,
+    kind: RunKind,
+    loaded: LoadedCosts,
+    /// See [`Records::runs_below_threshold`].
+    below_threshold: Option<f64>,
+    metadata: RunMetadata,
+    /// The total cost of the run for each event.
+    totals: Vec<i64>,
+    /// Whether the run recorded each event, if it did not record them all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recorded: Option<Vec<bool>>,
+    /// The statistics of the totals over the samples for each event, if the run is a sample
+    /// group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    totals_stats: Option<Vec<Option<SampleStats>>>,
+}
+
+/// A symbol of a JSON export.
+#[derive(Serialize, Deserialize)]
+struct JsonSymbol {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    object: Option<String>,
+    /// The self costs for each run, for each event (`costs[run][event]`), or `null` if unknown
+    /// (below the threshold of the run).
+    costs: Vec<Vec<Option<i64>>>,
+    /// The inclusive costs, laid out as [`Self::costs`], if any run has them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inclusive: Option<Vec<Vec<Option<i64>>>>,
+    /// See [`RecordsSymbol::renamed_from`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    renamed_from: Vec<String>,
+    /// The statistics of the main costs over the samples, laid out as [`Self::costs`], if any
+    /// run is a sample group. They are `null` for other runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stats: Option<Vec<Vec<Option<SampleStats>>>>,
+}
+
+/// Swap the two dimensions of costs (`costs[event][run]` into `costs[run][event]` and back).
+fn transpose<T: Copy>(costs: &[Vec<T>], n: usize) -> Vec<Vec<T>> {
+    (0..n).map(|i| costs.iter().map(|costs| costs[i]).collect()).collect()
+}
+
+/// Convert a `serde_json` error into a [`ParseError`], counting its lines from `first_line`.
+fn json_error(path: &str, first_line: usize, error: &serde_json::Error) -> ParseError {
+    // The position of the error is given by the `ParseError` instead.
+    let message = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+    let message = message.strip_suffix(&position).unwrap_or(&message);
+    ParseError::new(
+        path,
+        first_line + error.line().max(1) - 1,
+        ParseErrorKind::InvalidJson(message.to_string()),
+    )
+}
+
+impl Records {
+    /// Return the header of the JSON export of the records, without symbols.
+    fn json_header(&self) -> JsonHeader {
+        JsonHeader {
+            format: JSON_FORMAT.to_string(),
+            version: JSON_VERSION,
+            events: self.events.clone(),
+            runs: (0..self.n_runs())
+                .map(|run| JsonRun {
+                    name: self.run_names[run].clone(),
+                    kind: self.runs_kind[run],
+                    loaded: self.runs_loaded[run],
+                    below_threshold: self.runs_below_threshold[run],
+                    metadata: self.runs_metadata[run].clone(),
+                    totals: self.runs_totals.iter().map(|totals| totals[run]).collect(),
+                    recorded: self.runs_recorded.iter().any(|recorded| !recorded[run]).then(|| {
+                        self.runs_recorded.iter().map(|recorded| recorded[run]).collect()
+                    }),
+                    totals_stats: (!self.runs_metadata[run].samples.is_empty()).then(|| {
+                        self.runs_totals_stats.iter().map(|stats| stats[run]).collect()
+                    }),
+                })
+                .collect(),
+            symbols: None,
+        }
+    }
+
+    /// Return the JSON export of a symbol of the records.
+    fn json_symbol(&self, symbol: &RecordsSymbol) -> JsonSymbol {
+        let inclusive = self.runs_loaded.iter().any(|loaded| loaded.inclusive);
+        let samples = self.runs_metadata.iter().any(|metadata| !metadata.samples.is_empty());
+        JsonSymbol {
+            name: symbol.name.clone(),
+            file: symbol.file.clone(),
+            object: symbol.object.clone(),
+            costs: transpose(&symbol.costs, self.n_runs()),
+            inclusive: inclusive.then(|| transpose(&symbol.inclusive, self.n_runs())),
+            renamed_from: symbol.renamed_from.clone(),
+            stats: samples.then(|| transpose(&symbol.stats, self.n_runs())),
+        }
+    }
+
+    /// Export records to a JSON file, or an NDJSON file with one symbol per line.
+    ///
+    /// All events are exported, with the kind, metadata, totals and threshold of each run, and
+    /// the statistics of sample groups. Costs are given per run, then per event, and are `null`
+    /// when unknown. Annotated source lines are not exported.
+    pub fn to_json_file<P: AsRef<Path>>(&self, path: P, format: JsonFormat) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut header = self.json_header();
+        match format {
+            JsonFormat::Json => {
+                header.symbols =
+                    Some(self.symbols.iter().map(|symbol| self.json_symbol(symbol)).collect());
+                serde_json::to_writer_pretty(&mut writer, &header)?;
+                writeln!(writer)?;
+            }
+            JsonFormat::Ndjson => {
+                serde_json::to_writer(&mut writer, &header)?;
+                writeln!(writer)?;
+                for symbol in &self.symbols {
+                    serde_json::to_writer(&mut writer, &self.json_symbol(symbol))?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load records from a JSON or NDJSON input, as written by [`Self::to_json_file`].
+    ///
+    /// An NDJSON input is read line by line. With [`ParseOptions::lenient`], its malformed symbol
+    /// lines are skipped with a warning instead of failing the parse.
+    pub fn from_json(input: &Input, options: &ParseOptions) -> Result<Self> {
+        let mut lines = input.reader().lines().enumerate();
+
+        // The header of an NDJSON input is its first line, a JSON document spans several lines.
+        let first_line = lines.next().map(|(_, line)| line).transpose()?.unwrap_or_default();
+        let (header, ndjson) = match serde_json::from_str::<JsonHeader>(&first_line) {
+            Ok(header) => (header, true),
+            Err(_) => match serde_json::from_slice::<JsonHeader>(&input.content) {
+                Ok(header) => (header, false),
+                Err(error) => return Err(json_error(&input.path, 1, &error).into()),
+            },
+        };
+        let (mut records, symbols) = Self::from_json_header(header, &input.path)?;
+
+        for symbol in symbols {
+            let name = symbol.name.clone();
+            if let Err(error) = records.add_json_symbol(symbol, options) {
+                let error = format!("{}: symbol `{name}`: {error}", input.path);
+                if !options.lenient {
+                    bail!(error);
+                }
+                eprintln!("warning: {error} (skipped)");
+            }
+        }
+        for (i, line) in lines.filter(|_| ndjson) {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let symbol = serde_json::from_str::<JsonSymbol>(&line)
+                .map_err(|error| json_error(&input.path, i + 1, &error))
+                .and_then(|symbol| {
+                    records.add_json_symbol(symbol, options).map_err(|error| {
+                        ParseError::new(&input.path, i + 1, ParseErrorKind::InvalidJson(error))
+                    })
+                });
+            if let Err(error) = symbol {
+                if !options.lenient {
+                    return Err(error.into());
+                }
+                warn_skipped(&error);
+            }
+        }
+
+        records.assert_invariants();
+        Ok(records)
+    }
+
+    /// Set up empty records from the header of a JSON input.
+    ///
+    /// Returns the records, and the symbols of the header if any.
+    fn from_json_header(header: JsonHeader, path: &str) -> Result<(Self, Vec<JsonSymbol>)> {
+        if header.format != JSON_FORMAT {
+            bail!("{path}: not a JSON export of {JSON_FORMAT} (format {:?})", header.format);
+        }
+        if header.version > JSON_VERSION {
+            bail!(
+                "{path}: JSON export of version {}, only versions up to {JSON_VERSION} are read",
+                header.version
+            );
+        }
+
+        let n_events = header.events.len();
+        let mut records = Self::new();
+        records.events = header.events;
+        records.runs_totals = vec![Vec::new(); n_events];
+        records.runs_recorded = vec![Vec::new(); n_events];
+        records.runs_totals_stats = vec![Vec::new(); n_events];
+        for run in header.runs {
+            let totals_stats = run.totals_stats.unwrap_or_else(|| vec![None; n_events]);
+            let recorded = run.recorded.unwrap_or_else(|| vec![true; n_events]);
+            let lengths = [run.totals.len(), totals_stats.len(), recorded.len()];
+            if lengths.iter().any(|&length| length != n_events) {
+                bail!(
+                    "{path}: run `{}` has {} totals, for {n_events} events",
+                    run.name,
+                    lengths.into_iter().find(|&length| length != n_events).unwrap_or_default()
+                );
+            }
+            for (totals, total) in records.runs_totals.iter_mut().zip(run.totals) {
+                totals.push(total);
+            }
+            for (recorded, run_recorded) in records.runs_recorded.iter_mut().zip(recorded) {
+                recorded.push(run_recorded);
+            }
+            for (stats, total_stats) in records.runs_totals_stats.iter_mut().zip(totals_stats) {
+                stats.push(total_stats);
+            }
+            records.run_names.push(run.name);
+            records.runs_kind.push(run.kind);
+            records.runs_loaded.push(run.loaded);
+            records.runs_below_threshold.push(run.below_threshold);
+            records.runs_metadata.push(run.metadata);
+        }
+        Ok((records, header.symbols.unwrap_or_default()))
+    }
+
+    /// Add a symbol of a JSON input to the records.
+    ///
+    /// Returns why the symbol is malformed, if it is.
+    fn add_json_symbol(
+        &mut self,
+        symbol: JsonSymbol,
+        options: &ParseOptions,
+    ) -> Result<(), String> {
+        let (n_runs, n_events) = (self.n_runs(), self.events.len());
+        let has_shape = |lengths: Vec<usize>| {
+            lengths.len() == n_runs && lengths.iter().all(|&length| length == n_events)
+        };
+        let lengths = |costs: &[Vec<_>]| costs.iter().map(Vec::len).collect::<Vec<_>>();
+        if !has_shape(lengths(&symbol.costs))
+            || symbol.inclusive.as_deref().is_some_and(|costs| !has_shape(lengths(costs)))
+            || symbol.stats.as_ref().is_some_and(|stats| {
+                !has_shape(stats.iter().map(Vec::len).collect())
+            })
+        {
+            return Err(format!("expected costs for {n_runs} runs of {n_events} events each"));
+        }
+
+        self.symbols.push(RecordsSymbol {
+            name: options.rewrite_symbol(symbol.name),
+            file: symbol.file,
+            object: symbol.object,
+            costs: transpose(&symbol.costs, n_events),
+            inclusive: symbol.inclusive.map_or_else(
+                || vec![vec![Some(0); n_runs]; n_events],
+                |inclusive| transpose(&inclusive, n_events),
+            ),
+            renamed_from: symbol.renamed_from,
+            stats: symbol.stats.map_or_else(
+                || vec![vec![None; n_runs]; n_events],
+                |stats| transpose(&stats, n_events),
+            ),
+        });
+        Ok(())
+    }
+}
@@ -5,98 +5,345 @@
     clippy::cast_lossless
 )]
 
-use std::path::Path;
+use std::process::ExitCode;
 
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use itertools::Itertools;
 
 use crate::{
-    args::{Args, RelativeTo, SortByField},
-    display::display,
-    runs::{Records, Run},
+    args::{Args, GroupBy, MergeInto, Parts, RelativeTo, Show, SortByField},
+    display::{
+        display, display_check, display_metadata, display_rename_candidates, display_source,
+    },
+    input::{resolve_inputs, Input, InputKind, ResolvedInput},
+    renames::read_aliases,
+    runs::{Records, Run, RunKind},
 };
 
 mod args;
+mod cachegrind;
 mod callgrind;
+mod callgrind_out;
+mod check;
 mod display;
+mod input;
+mod json;
+mod normalize;
+mod renames;
 mod runs;
-
-/// Detect if a file is CSV by examining its content rather than extension.
-fn is_csv_file(path: &str) -> Result<bool> {
-    use std::fs::File;
-    use std::io::{BufRead, BufReader};
-    
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-    let mut first_line = String::new();
-    
-    if reader.read_line(&mut first_line)? == 0 {
-        return Ok(false); // Empty file
-    }
-    
-    // Check if first line looks like CSV (contains commas and no typical callgrind markers)
-    let line = first_line.trim();
-    if line.contains(',') && 
-       !line.contains("Profile data file") && 
-       !line.contains("Profiled target") && 
-       !line.contains("Events recorded") &&
-       !line.starts_with("Ir") &&
-       !line.starts_with("---") {
-        return Ok(true);
-    }
-    
-    Ok(false)
-}
+mod stats;
 
 /// Parse inputs from the configuration into a [`Records`].
 ///
-/// Files are detected as CSV or `callgrind_annotate` based on content, not extension.
-/// CSV files are loaded as multiple runs, `callgrind_annotate` files as single runs.
+/// Files are detected as CSV, `callgrind_annotate`, raw `callgrind.out`, `cg_annotate` or raw
+/// `cachegrind.out` based on content, not extension, unless their kind is given. CSV files are
+/// loaded as multiple runs, other files as single runs, named as per [`resolve_inputs`].
+///
+/// `-` reads the standard input. Gzip, zstd and xz inputs are decompressed before detection.
+///
+/// The runs of `--merge-into` come next, each merged from its inputs (see
+/// [`Run::merge_listings`]), such as the self and inclusive listings of the same profile, and
+/// those of `--sample-group` last (see [`Run::from_samples`]). The parts and threads of the same
+/// process are then labeled or summed, as per `--parts`. Finally, the rows of the symbols renamed
+/// as per `--aliases` are merged.
 fn parse_records(config: &Args) -> Result<Records> {
-    let mut records = Records::new();
-    let mut callgrind_file_count = 0;
-    
-    for input in &config.inputs {
-        if is_csv_file(input)? {
-            // Load CSV file and merge its records
-            let csv_records = Records::from_csv_file(input, &config.string_replace)?;
-            for (i, run_name) in csv_records.run_names.iter().enumerate() {
-                let mut run = Run::new_named(run_name.clone());
-                run.total_ir = csv_records.runs_total_irs[i];
-                
-                for symbol in &csv_records.symbols {
-                    if symbol.irs[i] > 0 {
-                        run.add_ir(&symbol.name, symbol.irs[i]);
-                    }
+    let mut runs: Vec<Run> = Vec::new();
+    // The index of the argument each run comes from.
+    let mut origins = Vec::new();
+
+    let inputs = resolve_inputs(&config.inputs, &config.csv_names, &config.input_order)?;
+    for ResolvedInput { input, kind, name, spec } in inputs {
+        if kind.holds_runs() {
+            // Load CSV or JSON file and merge its records
+            let file_records = if kind == InputKind::Json {
+                Records::from_json(&input, &config.parse_options())?
+            } else {
+                Records::from_csv(&input, &config.parse_options())?
+            };
+            if name.is_some() && file_records.n_runs() != 1 {
+                bail!(
+                    "{} holds {} runs, only an input with a single run can be named",
+                    input.path,
+                    file_records.n_runs()
+                );
+            }
+            for i in 0..file_records.n_runs() {
+                let mut run = file_records.run(i);
+                group_loaded_run(&mut run, &input.path, config)?;
+                if let Some(name) = &name {
+                    run.name.clone_from(name);
                 }
-                
-                records.add_run(run);
+                runs.push(run);
+                origins.push(spec);
             }
         } else {
-            // Load callgrind annotate file
-            let mut run = Run::from_callgrind_annotate_file(input, &config.string_replace)?;
-            
-            // Apply custom name if available
-            if callgrind_file_count < config.csv_names.len() {
-                run.name.clone_from(&config.csv_names[callgrind_file_count]);
-            } else if run.name.is_empty() {
-                // If no name provided and run doesn't have a name, use filename
-                run.name = Path::new(input)
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or(input)
-                    .to_string();
+            // Load callgrind annotate or raw callgrind file
+            let mut run = load_run(&input, kind, config)?;
+            if config.group_unresolved {
+                run.group_unresolved();
+            }
+            run.group_by(config.group_by);
+            run.name = name.expect("Inputs other than CSV are always named");
+            runs.push(run);
+            origins.push(spec);
+        }
+    }
+
+    for (i, merge) in config.merge_into.iter().enumerate() {
+        let mut listings = Vec::new();
+        let inputs = resolve_inputs(&merge.inputs, &[], &config.input_order)?;
+        for ResolvedInput { input, kind, .. } in inputs {
+            if kind.holds_runs() {
+                bail!(
+                    "{} is a {} input, which cannot be merged into {}",
+                    input.path,
+                    if kind == InputKind::Json { "JSON" } else { "CSV" },
+                    merge.name
+                );
             }
-            
-            records.add_run(run);
-            callgrind_file_count += 1;
+            let mut run = load_run(&input, kind, config)?;
+            run.name = input.path;
+            listings.push(run);
         }
+        // Symbols are merged as listed, before a partial listing is added up by `--group-by`.
+        let (mut run, conflicts) = Run::merge_listings(merge.name.clone(), listings)?;
+        if config.group_unresolved {
+            run.group_unresolved();
+        }
+        run.group_by(config.group_by);
+        if config.strict && !conflicts.is_empty() {
+            bail!("{} (--strict)", conflicts.join("\n"));
+        }
+        for conflict in conflicts {
+            eprintln!("warning: {conflict}");
+        }
+        runs.push(run);
+        origins.push(config.inputs.len() + i);
+    }
+
+    for (i, group) in config.sample_group.iter().enumerate() {
+        runs.push(load_sample_group(group, config)?);
+        origins.push(config.inputs.len() + config.merge_into.len() + i);
+    }
+
+    match config.parts {
+        Parts::Separate => label_parts(&mut runs),
+        Parts::Sum | Parts::Command => runs = sum_parts(runs, &origins, config)?,
+    }
+
+    let mut records = Records::new();
+    for run in runs {
+        records.add_run(run);
+    }
+    if let Some(path) = &config.aliases {
+        records.merge_aliases(&read_aliases(path)?);
     }
     Ok(records)
 }
 
-fn main() -> Result<()> {
-    let config = Args::parse().validated()?;
+/// Group the symbols of a run loaded from a CSV or JSON input, as those of other inputs are.
+///
+/// Fails if the input does not keep the files or objects that `--group-by` needs.
+fn group_loaded_run(run: &mut Run, path: &str, config: &Args) -> Result<()> {
+    if config.group_unresolved {
+        run.group_unresolved();
+    }
+    if !run.can_group_by(config.group_by) {
+        let what = match config.group_by {
+            GroupBy::File | GroupBy::FileFunction => "files",
+            _ => "objects",
+        };
+        bail!("{path} does not keep the {what} of its symbols, which --group-by needs");
+    }
+    run.group_by(config.group_by);
+    Ok(())
+}
+
+/// Load the samples of a `--sample-group` as a single run (see [`Run::from_samples`]).
+fn load_sample_group(group: &MergeInto, config: &Args) -> Result<Run> {
+    let mut samples = Vec::new();
+    let inputs = resolve_inputs(&group.inputs, &[], &config.input_order)?;
+    for ResolvedInput { input, kind, .. } in inputs {
+        if kind.holds_runs() {
+            bail!(
+                "{} is a {} input, which cannot be a sample of {}",
+                input.path,
+                if kind == InputKind::Json { "JSON" } else { "CSV" },
+                group.name
+            );
+        }
+        let mut run = load_run(&input, kind, config)?;
+        if config.group_unresolved {
+            run.group_unresolved();
+        }
+        run.group_by(config.group_by);
+        run.name = input.path;
+        samples.push(run);
+    }
+    Run::from_samples(group.name.clone(), &samples)
+}
+
+/// Return the process a profile run was taken from (its command line and PID), if known.
+fn process(run: &Run) -> Option<(String, u64)> {
+    let metadata = &run.metadata;
+    Some((metadata.command.clone()?, metadata.pid?)).filter(|_| run.kind == RunKind::Profile)
+}
+
+/// Append their part and thread to the names of the runs of the same process, when they are not
+/// all of the same part and thread (`--parts separate`).
+fn label_parts(runs: &mut [Run]) {
+    let processes = runs
+        .iter()
+        .map(|run| process(run).map(|process| (process, run.metadata.parts_label())))
+        .collect::<Vec<_>>();
+    for (run, process) in runs.iter_mut().zip(&processes) {
+        let Some((process, label)) = process else {
+            continue;
+        };
+        let other_parts = processes
+            .iter()
+            .flatten()
+            .any(|(x, x_label)| x == process && x_label != label);
+        if let Some(label) = label.as_ref().filter(|_| other_parts) {
+            run.name = format!("{} {label}", run.name);
+        }
+    }
+}
+
+/// Add up the runs of the same process (`--parts sum`), or of the same command line among the
+/// runs of the same argument (`--parts command`), into the first one. `origins` holds the argument
+/// each run comes from.
+///
+/// With `--parts-breakdown`, the runs that were added up follow the sum as breakdown runs.
+///
+/// Fails if two runs hold the same part of the same thread of a process, such as outputs of the
+/// same profile at different thresholds (see `--merge-into`).
+fn sum_parts(runs: Vec<Run>, origins: &[usize], config: &Args) -> Result<Vec<Run>> {
+    let group = |run: &Run, origin: usize| {
+        let (command, pid) = process(run)?;
+        Some(match config.parts {
+            Parts::Command => (command, None, Some(origin)),
+            _ => (command, Some(pid), None),
+        })
+    };
+    // Each sum, the argument it comes from and the runs added up into it.
+    let mut groups: Vec<(Run, usize, Vec<Run>)> = Vec::new();
+    for (run, &origin) in runs.into_iter().zip(origins) {
+        let same = group(&run, origin).and_then(|key| {
+            groups.iter().position(|(sum, origin, _)| group(sum, *origin).as_ref() == Some(&key))
+        });
+        let Some(i) = same else {
+            groups.push((run.clone(), origin, vec![run]));
+            continue;
+        };
+        let (sum, _, parts) = &mut groups[i];
+        let part = |run: &Run| (run.metadata.pid, run.metadata.parts_label());
+        if let Some(same_part) = parts.iter().find(|x| part(x) == part(&run)) {
+            bail!(
+                "{} and {} hold the same part of the same process, they cannot be summed",
+                same_part.name,
+                run.name
+            );
+        }
+        sum.add_part(run.clone())?;
+        parts.push(run);
+    }
+
+    let mut summed = Vec::new();
+    for (sum, _, mut parts) in groups {
+        summed.push(sum);
+        if config.parts_breakdown && parts.len() > 1 {
+            for part in &mut parts {
+                part.name = format!("pid {}", part.metadata.pid.unwrap_or_default());
+            }
+            label_parts(&mut parts);
+            for part in &mut parts {
+                part.kind = RunKind::Breakdown;
+            }
+            summed.extend(parts);
+        }
+    }
+    Ok(summed)
+}
+
+/// Load a run from an input other than CSV or JSON.
+fn load_run(input: &Input, kind: InputKind, config: &Args) -> Result<Run> {
+    let options = config.parse_options();
+    Ok(match kind {
+        InputKind::CallgrindOut => Run::from_callgrind_out(input, &options)?,
+        InputKind::CgAnnotate => Run::from_cg_annotate(input, &options)?,
+        InputKind::CachegrindOut => Run::from_cachegrind_out(input, &options)?,
+        InputKind::CallgrindAnnotate => Run::from_callgrind_annotate(input, &options)?,
+        InputKind::Csv | InputKind::Json => {
+            unreachable!("CSV and JSON files are loaded as records")
+        }
+    })
+}
+
+/// Check that the costs needed by `--show` were loaded for every run.
+///
+/// If no run holds self costs but all hold inclusive costs, self columns are shown as inclusive
+/// ones instead.
+fn resolve_show(config: &mut Args, records: &Records) -> Result<()> {
+    if !records.has_self_costs() && records.has_inclusive_costs() {
+        config.show = config.show.iter().copied().map(Show::inclusive).unique().collect();
+    }
+
+    let missing = if config.show.iter().any(|show| !show.is_inclusive()) {
+        records.runs_loaded.iter().position(|loaded| !loaded.self_cost)
+    } else {
+        None
+    };
+    if let Some(run) = missing {
+        bail!(
+            "Run {} holds no self costs, use `--show` with inclusive columns only",
+            records.run_names[run]
+        );
+    }
+
+    let missing = if config.show.iter().any(|show| show.is_inclusive()) {
+        records.runs_loaded.iter().position(|loaded| !loaded.inclusive)
+    } else {
+        None
+    };
+    if let Some(run) = missing {
+        bail!(
+            "Run {} holds no inclusive costs (load a raw callgrind file, or the output of \
+             `callgrind_annotate --inclusive=yes`)",
+            records.run_names[run]
+        );
+    }
+    Ok(())
+}
+
+/// Resolve the events selected with `--event` into indices in the records.
+///
+/// The first index is the one used for sorting.
+fn selected_events(config: &Args, records: &Records) -> Result<Vec<usize>> {
+    if config.event.is_empty() {
+        return Ok(vec![records.event_index("Ir").unwrap_or(0)]);
+    }
+    config
+        .event
+        .iter()
+        .map(|event| {
+            records.event_index(event).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown event {event} (recorded events: {})",
+                    records.events.join(", ")
+                )
+            })
+        })
+        .collect()
+}
+
+fn main() -> Result<ExitCode> {
+    // Invalid arguments exit with the same code as those rejected by the parser (2).
+    let mut config = Args::parse()
+        .validated()
+        .unwrap_or_else(|error| Args::command().error(ErrorKind::ValueValidation, error).exit());
+    config.load_regex_replace_file()?;
     let mut records = parse_records(&config)?;
     if records.n_runs() == 0 {
         bail!("No input run");
@@ -105,6 +352,16 @@ fn main() -> Result<()> {
         if (*x as usize) >= records.n_runs() {
             bail!("--relative-to column index out of range");
         }
+        if records.runs_kind[*x as usize] != RunKind::Profile {
+            bail!(
+                "--relative-to column {x} is a {} run ({}), which is not compared",
+                match records.runs_kind[*x as usize] {
+                    RunKind::Breakdown => "breakdown",
+                    _ => "delta",
+                },
+                records.run_names[*x as usize]
+            );
+        }
     }
     if let SortByField::ColumnIR(x) = &config.sort_by.field {
         if (*x as usize) >= records.n_runs() {
@@ -112,15 +369,51 @@ fn main() -> Result<()> {
         }
     }
 
-    records.sort(config.sort_by)?;
-    display(&config, &records);
+    let events = selected_events(&config, &records)?;
+    resolve_show(&mut config, &records)?;
+
+    let mismatches = records.metadata_mismatches();
+    if config.strict && !mismatches.is_empty() {
+        bail!("{} (--strict)", mismatches.join("\n"));
+    }
+    for mismatch in mismatches {
+        eprintln!("warning: {mismatch}");
+    }
+
+    records.sort(config.sort_by, events[0])?;
+    if config.show_metadata {
+        display_metadata(&records);
+    }
+    if config.detect_renames {
+        display_rename_candidates(&records.rename_candidates(events[0]));
+    }
+    if config.source.is_empty() {
+        display(&config, &records, &events);
+    } else {
+        let Some(lines) = records.source_lines(&config.source) else {
+            bail!(
+                "No annotated source for {} (annotated files: {})",
+                config.source,
+                records.lines.iter().map(|line| &line.file).unique().join(", ")
+            );
+        };
+        display_source(&config, &records, &lines, events[0]);
+    }
+    let report = config.check.then(|| records.check(&config, events[0]));
+    if let Some(report) = &report {
+        display_check(report);
+    }
+
+    if !config.json_export.is_empty() {
+        records.to_json_file(&config.json_export, config.json_format)?;
+    }
 
     // Export to CSV if requested
     if !config.csv_export.is_empty() {
         // Determine reference column for calculations
         let reference_column = match &config.relative_to {
-            RelativeTo::Last => records.n_runs().saturating_sub(1),
-            RelativeTo::Previous | RelativeTo::First => 0, // For previous, we'll use first as reference in CSV
+            RelativeTo::Last => records.profile_runs().next_back().unwrap_or(0),
+            RelativeTo::Previous | RelativeTo::First => records.profile_runs().next().unwrap_or(0), // For previous, we'll use first as reference in CSV
             RelativeTo::Column(x) => (*x as usize).min(records.n_runs().saturating_sub(1)),
         };
 
@@ -131,11 +424,12 @@ fn main() -> Result<()> {
                 config.csv_differences,
                 config.csv_all_data,
                 reference_column,
+                &events,
             )?;
         } else {
-            records.to_csv_file(&config.csv_export)?;
+            records.to_csv_file(&config.csv_export, &events)?;
         }
     }
 
-    Ok(())
+    Ok(report.map_or(ExitCode::SUCCESS, |report| report.exit_code()))
 }
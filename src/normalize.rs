@@ -0,0 +1,155 @@
+use crate::args::Normalize;
+
+/// Normalize a symbol name with the given passes.
+///
+/// Passes are run in the order of [`Normalize`], whatever the order they are given in, so that
+/// names are demangled before the other passes.
+pub fn normalize(mut name: String, passes: &[Normalize]) -> String {
+    for pass in [
+        Normalize::Demangle,
+        Normalize::Hashes,
+        Normalize::Closures,
+        Normalize::Generics,
+    ] {
+        if !passes.contains(&pass) {
+            continue;
+        }
+        // Callgrind tells apart the levels of a recursion cycle with a `'<level>` suffix.
+        let (symbol, suffix) = match name.rfind('\'') {
+            Some(i) if name[i + 1..].chars().all(|c| c.is_ascii_digit()) => name.split_at(i),
+            _ => (name.as_str(), ""),
+        };
+        let symbol = match pass {
+            Normalize::Demangle => demangle(symbol),
+            Normalize::Hashes => strip_hashes(symbol),
+            Normalize::Closures => normalize_closures(symbol),
+            Normalize::Generics => collapse_generics(symbol),
+        };
+        if let Some(symbol) = symbol {
+            name = symbol + suffix;
+        }
+    }
+    name
+}
+
+/// Demangle a Rust (legacy or v0) or Itanium C++ mangled name.
+///
+/// Returns `None` if the name is not mangled.
+fn demangle(symbol: &str) -> Option<String> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+        // Legacy Rust names are Itanium C++ names of a kind, and end with their hash.
+        if !symbol.starts_with("_ZN") || is_rust_hash(&demangled.to_string()) {
+            return Some(demangled.to_string());
+        }
+    }
+    if !symbol.starts_with("_Z") {
+        return None;
+    }
+    cpp_demangle::Symbol::new(symbol).ok()?.demangle().ok()
+}
+
+/// Return whether the demangled name ends with the hash of a legacy Rust name (`::h<16 digits>`).
+fn is_rust_hash(name: &str) -> bool {
+    name.rsplit_once("::h")
+        .is_some_and(|(_, hash)| hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Strip the hashes of Rust names: the `::h<hash>` suffix of legacy names, and the `[<hash>]`
+/// crate disambiguators of v0 names.
+///
+/// Returns `None` if the name has no hash.
+fn strip_hashes(symbol: &str) -> Option<String> {
+    let mut stripped = String::with_capacity(symbol.len());
+    let mut rest = symbol;
+    while let Some(i) = rest.find('[') {
+        // Disambiguators follow the name of a crate, unlike the sizes of C++ arrays (`int [4]`).
+        let hash = rest[i + 1..]
+            .split_once(']')
+            .filter(|_| rest[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+            .map(|(hash, _)| hash);
+        match hash {
+            Some(hash) if !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                stripped.push_str(&rest[..i]);
+                rest = &rest[i + hash.len() + 2..];
+            }
+            _ => {
+                stripped.push_str(&rest[..=i]);
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    if is_rust_hash(&stripped) {
+        stripped.truncate(stripped.rfind("::h").unwrap());
+    }
+    (stripped != symbol).then_some(stripped)
+}
+
+/// Give all closures (and other anonymous items) of a function the same name: `{{closure}}` of
+/// legacy Rust names and `{closure#<index>}` of v0 names become `{closure}`.
+///
+/// Returns `None` if the name has no closure.
+fn normalize_closures(symbol: &str) -> Option<String> {
+    let mut normalized = String::with_capacity(symbol.len());
+    let mut rest = symbol.replace("{{closure}}", "{closure}");
+    while let Some(i) = rest.find('{') {
+        normalized.push_str(&rest[..=i]);
+        rest = rest.split_off(i + 1);
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let (kind, index) = rest[..end].split_once('#').unwrap_or((&rest[..end], ""));
+        if !kind.is_empty() && index.chars().all(|c| c.is_ascii_digit()) {
+            normalized.push_str(kind);
+            rest = rest.split_off(end);
+        }
+    }
+    normalized.push_str(&rest);
+    (normalized != symbol).then_some(normalized)
+}
+
+/// Collapse the generic parameters of types (`Vec<u8>`, `std::vector<int, std::allocator<int> >`)
+/// into `<_>`, and remove those of Rust functions (`foo::<u8>`), which legacy Rust names do not
+/// have.
+///
+/// Qualified paths (`<T as Trait>::method`, `<impl Trait for Type>::method`) and C++ operators
+/// (`operator<`) are kept as they are. Returns `None` if the name has no generic parameters.
+fn collapse_generics(symbol: &str) -> Option<String> {
+    let mut collapsed = String::with_capacity(symbol.len());
+    let mut chars = symbol.char_indices();
+    while let Some((i, c)) = chars.next() {
+        let generic = c == '<'
+            && (collapsed.ends_with(|c: char| c.is_alphanumeric() || c == '_')
+                || collapsed.ends_with("::"))
+            && !collapsed.ends_with("operator")
+            && !collapsed.ends_with("operator<");
+        if !generic {
+            collapsed.push(c);
+            continue;
+        }
+        // Skip until the matching `>`, ignoring those of `->`.
+        let mut depth = 1;
+        let mut previous = c;
+        for (_, c) in chars.by_ref() {
+            match c {
+                '<' => depth += 1,
+                '>' if previous != '-' => depth -= 1,
+                _ => {}
+            }
+            previous = c;
+            if depth == 0 {
+                break;
+            }
+        }
+        if depth != 0 {
+            // Unbalanced, this was not a list of parameters after all.
+            collapsed.push_str(&symbol[i..]);
+            break;
+        }
+        match collapsed.strip_suffix("::") {
+            Some(function) => collapsed.truncate(function.len()),
+            None => collapsed.push_str("<_>"),
+        }
+    }
+    (collapsed != symbol).then_some(collapsed)
+}
@@ -0,0 +1,236 @@
+use anyhow::{bail, Context, Result};
+
+use crate::{runs::Records, stats::SampleStats};
+
+/// The similarity that the names of a possible rename must exceed, from 0 to 1 (see
+/// [`name_similarity`]): more than half of their path must be alike.
+const MIN_NAME_SIMILARITY: f64 = 0.5;
+
+/// What a possible rename gains in ranking when the last segments of the paths of both names are
+/// the same (`a::foo` to `b::foo`), as for a function moved to another module.
+const SAME_LAST_SEGMENT_BONUS: f64 = 0.25;
+
+/// The lowest ratio between the costs of a possible rename, before and after (smallest over
+/// largest).
+const MIN_COST_RATIO: f64 = 0.5;
+
+/// A symbol that was renamed or moved, as given in an alias file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    /// The name of the symbol before it was renamed.
+    pub old: String,
+    /// The name of the symbol after it was renamed.
+    pub new: String,
+}
+
+/// Read the aliases of a file, one `old=>new` per line.
+///
+/// Names are trimmed. Empty lines and lines starting with `#` are ignored.
+pub fn read_aliases(path: &str) -> Result<Vec<Alias>> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Could not read {path}"))?;
+    let mut aliases = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((old, new)) = line.split_once("=>") else {
+            bail!("{path}:{}: no '=>' in alias {line:?}", i + 1);
+        };
+        aliases.push(Alias {
+            old: old.trim().to_string(),
+            new: new.trim().to_string(),
+        });
+    }
+    Ok(aliases)
+}
+
+/// Merge the cost of a symbol with that of the symbol it was renamed from, in the same run.
+///
+/// A run usually lists one of the names only, whose cost is kept. The cost is unknown if neither
+/// name has a known cost other than 0.
+fn merge_cost(cost: Option<i64>, old_cost: Option<i64>) -> Option<i64> {
+    match (cost, old_cost) {
+        (Some(cost), Some(old_cost)) => Some(cost + old_cost),
+        (Some(cost), None) | (None, Some(cost)) if cost != 0 => Some(cost),
+        _ => None,
+    }
+}
+
+/// Merge the statistics of the cost of a symbol with those of the symbol it was renamed from, in
+/// the same sample group.
+///
+/// The statistics of the samples that listed both names are unknown: those of the name listed
+/// alone are kept, unless the other one has a cost in some sample.
+fn merge_stats(stats: Option<SampleStats>, old_stats: Option<SampleStats>) -> Option<SampleStats> {
+    match (stats, old_stats) {
+        (Some(stats), Some(other)) | (Some(other), Some(stats)) if other.max == 0 => Some(stats),
+        _ => None,
+    }
+}
+
+/// Return how similar two names are, from 0 (nothing in common) to 1 (the same).
+///
+/// Names are compared as paths: this is 1 minus the edit distance between their `::` segments over
+/// the number of segments of the longest one, where replacing a segment costs how dissimilar it
+/// is (see [`segment_similarity`]). Every segment counts, so that `a::Foo::new` and `b::Bar::new`
+/// are not alike, whereas `crate::a::foo` and `crate::b::foo` are.
+fn name_similarity(old: &str, new: &str) -> f64 {
+    let (old, new) = (old.split("::").collect::<Vec<_>>(), new.split("::").collect::<Vec<_>>());
+    let distance = edit_distance(&old, &new, |old, new| 1.0 - segment_similarity(old, new));
+    1.0 - distance / old.len().max(new.len()) as f64
+}
+
+/// Return how similar two segments of names are, from 0 to 1: 1 minus the edit distance between
+/// their characters over the length of the longest one.
+fn segment_similarity(old: &str, new: &str) -> f64 {
+    let (old, new) = (old.chars().collect::<Vec<_>>(), new.chars().collect::<Vec<_>>());
+    let distance = edit_distance(&old, &new, |old, new| f64::from(u8::from(old != new)));
+    let longest = old.len().max(new.len()).max(1);
+    1.0 - distance / longest as f64
+}
+
+/// Return whether the last `::` segments of two names are the same.
+fn same_last_segment(old: &str, new: &str) -> bool {
+    old.contains("::") && old.rsplit("::").next() == new.rsplit("::").next()
+}
+
+/// Return the edit distance between two sequences, where inserting or removing an item costs 1 and
+/// replacing one costs `substitution` (from 0 to 1).
+fn edit_distance<T>(old: &[T], new: &[T], substitution: impl Fn(&T, &T) -> f64) -> f64 {
+    // The distances between the prefixes of `old` and those of `new`, row by row.
+    let mut distances = (0..=new.len()).map(|j| j as f64).collect::<Vec<_>>();
+    for (i, old_item) in old.iter().enumerate() {
+        let mut diagonal = distances[0];
+        distances[0] = (i + 1) as f64;
+        for (j, new_item) in new.iter().enumerate() {
+            let replaced = diagonal + substitution(old_item, new_item);
+            diagonal = distances[j + 1];
+            distances[j + 1] = replaced.min(distances[j] + 1.0).min(diagonal + 1.0);
+        }
+    }
+    distances[new.len()]
+}
+
+impl Records {
+    /// Merge the row of each symbol renamed as per `aliases` into the row of its new name, which
+    /// keeps track of the old name.
+    ///
+    /// Names are keys, as shown (see [`crate::runs::RecordsSymbol::key`]). Aliases apply in
+    /// order, so that a symbol renamed twice can be given as `a=>b` then `b=>c`. Aliases of
+    /// which either name is not a symbol of the records are ignored.
+    pub fn merge_aliases(&mut self, aliases: &[Alias]) {
+        for alias in aliases {
+            let position = |key: &str| self.symbols.iter().position(|symbol| symbol.key() == key);
+            let (Some(old), Some(new)) = (position(&alias.old), position(&alias.new)) else {
+                continue;
+            };
+            if old == new {
+                continue;
+            }
+            let old = self.symbols.remove(old);
+            let symbol = self
+                .symbols
+                .iter_mut()
+                .find(|symbol| symbol.key() == alias.new)
+                .expect("The new symbol is still there");
+            for (costs, old_costs) in [
+                (&mut symbol.costs, &old.costs),
+                (&mut symbol.inclusive, &old.inclusive),
+            ] {
+                for (costs, old_costs) in costs.iter_mut().zip(old_costs) {
+                    for (cost, &old_cost) in costs.iter_mut().zip(old_costs) {
+                        *cost = merge_cost(*cost, old_cost);
+                    }
+                }
+            }
+            for (stats, old_stats) in symbol.stats.iter_mut().zip(&old.stats) {
+                for (stats, &old_stats) in stats.iter_mut().zip(old_stats) {
+                    *stats = merge_stats(*stats, old_stats);
+                }
+            }
+            let key = old.key();
+            symbol.renamed_from.extend(old.renamed_from);
+            symbol.renamed_from.push(key);
+        }
+        self.assert_invariants();
+    }
+
+    /// Propose symbols that may have been renamed, as `(old, new)` keys, best first.
+    ///
+    /// Among the profile runs, the old symbol has a cost but none in the last run, and the new
+    /// one has none in the first run but a cost later, never in the same run as the old one.
+    /// Their names must be similar enough (see [`name_similarity`]), and so must their costs
+    /// for `event` in the last run of the old symbol and the first run of the new one. Pairs are
+    /// ranked by both, with a bonus for names ending with the same segment. Each symbol is
+    /// proposed once at most.
+    pub fn rename_candidates(&self, event: usize) -> Vec<(String, String)> {
+        let runs = self.profile_runs().collect::<Vec<_>>();
+        let (Some(&first), Some(&last)) = (runs.first(), runs.last()) else {
+            return Vec::new();
+        };
+        // The runs in which each symbol has a cost.
+        let present = self
+            .symbols
+            .iter()
+            .map(|symbol| {
+                let costs = &self.main_costs(symbol)[event];
+                runs.iter()
+                    .copied()
+                    .filter(|&run| costs[run].is_some_and(|cost| cost != 0))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // A symbol is absent from a run that recorded the event but did not list it, as opposed
+        // to one whose cost is unknown there.
+        let absent = |symbol: usize, run: usize| {
+            self.runs_recorded[event][run]
+                && self.main_costs(&self.symbols[symbol])[event][run] == Some(0)
+        };
+        let cost = |symbol: usize, run: usize| {
+            self.main_costs(&self.symbols[symbol])[event][run].unwrap_or(0).abs() as f64
+        };
+
+        let mut candidates = Vec::new();
+        for old in (0..self.symbols.len()).filter(|&old| absent(old, last)) {
+            let Some(&old_last) = present[old].last() else {
+                continue;
+            };
+            for new in (0..self.symbols.len()).filter(|&new| absent(new, first)) {
+                let Some(&new_first) = present[new].first() else {
+                    continue;
+                };
+                if present[new].iter().any(|run| present[old].contains(run)) {
+                    continue;
+                }
+                let (old_cost, new_cost) = (cost(old, old_last), cost(new, new_first));
+                let cost_ratio = old_cost.min(new_cost) / old_cost.max(new_cost);
+                if cost_ratio < MIN_COST_RATIO {
+                    continue;
+                }
+                let (old_name, new_name) = (&self.symbols[old].name, &self.symbols[new].name);
+                let similarity = name_similarity(old_name, new_name);
+                if similarity > MIN_NAME_SIMILARITY {
+                    let bonus = if same_last_segment(old_name, new_name) {
+                        SAME_LAST_SEGMENT_BONUS
+                    } else {
+                        0.0
+                    };
+                    candidates.push((similarity + cost_ratio + bonus, old, new));
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut proposed = vec![false; self.symbols.len()];
+        let mut renames = Vec::new();
+        for (_, old, new) in candidates {
+            if !proposed[old] && !proposed[new] {
+                proposed[old] = true;
+                proposed[new] = true;
+                renames.push((self.symbols[old].key(), self.symbols[new].key()));
+            }
+        }
+        renames
+    }
+}
@@ -1,18 +1,164 @@
-use std::{fs::File, io::BufReader, path::Path};
+use std::{borrow::Cow, collections::HashMap, fs::File, iter, path::Path};
 
 use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 
-use crate::args::{SortBy, SortByField, SortByOrder, StringReplacement};
+use crate::{
+    args::{GroupBy, ParseOptions, SortBy, SortByField, SortByOrder},
+    callgrind::{warn_skipped, ParseError, ParseErrorKind},
+    input::Input,
+    stats::SampleStats,
+};
+
+/// The name of the row holding the total costs of the runs in CSV exports, after the
+/// `callgrind_annotate` line of the same name.
+const CSV_TOTALS: &str = "PROGRAM TOTALS";
 
 /// Annotations of a run of a binary.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Run {
     // The name of the run, if any. This is purely for human readability purposes.
     pub name: String,
-    /// The symbols that were hit and their instruction count.
+    /// The events recorded for this run (`Ir`, `Dr`, `I1mr`, ...).
+    ///
+    /// This gives the order of the costs in [`Self::totals`] and [`AnnotatedSymbol::costs`].
+    pub events: Vec<String>,
+    /// The symbols that were hit and their cost for each event.
     pub symbols: Vec<AnnotatedSymbol>,
-    /// The total number of IR for this run.
-    pub total_ir: u64,
+    /// The total cost of this run for each event.
+    pub totals: Vec<i64>,
+    /// Which of the self and inclusive costs of the symbols were loaded.
+    pub loaded: LoadedCosts,
+    /// The lines of annotated source and their costs, if any.
+    pub lines: Vec<AnnotatedLine>,
+    /// Whether the costs are those of a profile, or the difference between two profiles.
+    pub kind: RunKind,
+    /// How the profile was produced, as read from its header.
+    pub metadata: RunMetadata,
+    /// The percentage of the totals under which symbols may have been left out of the listing
+    /// (see [`RunMetadata::below_threshold`]), or `None` if the listing is complete.
+    pub below_threshold: Option<f64>,
+    /// The statistics of the totals over the samples, for each event, if the run is a sample
+    /// group (see [`Run::from_samples`]).
+    pub totals_stats: Option<Vec<SampleStats>>,
+}
+
+/// What the costs of a run are.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunKind {
+    /// The costs of a profile.
+    #[default]
+    Profile,
+    /// The difference between the costs of two profiles (as given by `cg_diff`), which may be
+    /// negative.
+    ///
+    /// Delta runs are shown as is, rather than compared to a reference run.
+    Delta,
+    /// The costs of one of the processes, parts or threads added up into the profile run before
+    /// it, kept as a drill-down (see `--parts-breakdown`).
+    ///
+    /// Breakdown runs are shown as is, rather than compared to a reference run.
+    Breakdown,
+}
+
+/// How the profile of a run was produced, as far as its header tells.
+///
+/// Fields that the input does not hold are empty.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunMetadata {
+    /// The profile data file the output was made from (`callgrind.out.<pid>`).
+    pub data_file: Option<String>,
+    /// The tool and the Valgrind version that wrote the profile (`callgrind-3.18.1`).
+    pub creator: Option<String>,
+    /// The command line of the profiled program.
+    pub command: Option<String>,
+    /// The PID of the profiled program.
+    pub pid: Option<u64>,
+    /// The parts of the profile the costs come from, in increasing order.
+    ///
+    /// Callgrind dumps a new part of the profile each time it is asked to (`--dump-every-bb`,
+    /// `callgrind_control -d`, ...). A run holds several parts once they are summed.
+    pub parts: Vec<u32>,
+    /// The threads the costs come from (`--separate-threads=yes`), in increasing order.
+    pub threads: Vec<u32>,
+    /// The events recorded during profiling, which may be more than those shown and loaded.
+    pub events_recorded: Vec<String>,
+    /// The thresholds given to `callgrind_annotate` or `cg_annotate`.
+    pub thresholds: Option<String>,
+    /// What triggered the dump of the profile (`Program termination`).
+    pub trigger: Option<String>,
+    /// Whether the function table holds inclusive costs, if the header tells (`--inclusive=yes`).
+    pub inclusive: Option<bool>,
+    /// The names of the samples whose mean the run is, if it is a sample group.
+    pub samples: Vec<String>,
+}
+
+impl RunMetadata {
+    /// Return the percentage of the totals under which symbols may have been left out of the
+    /// listing, as per the thresholds, or `None` if the listing is complete.
+    ///
+    /// `callgrind_annotate` lists symbols until they add up to its threshold (99% by default), so
+    /// each one left out costs less than what remains (1%): its thresholds are `cumulative`.
+    /// `cg_annotate` leaves out each symbol that costs less than its threshold (0.1% by default).
+    pub fn below_threshold(&self, cumulative: bool) -> Option<f64> {
+        let threshold = self
+            .thresholds
+            .as_deref()?
+            .split_whitespace()
+            .next()?
+            .trim_end_matches('%')
+            .parse::<f64>()
+            .ok()?;
+        let below = if cumulative { 100.0 - threshold } else { threshold };
+        (below > 0.0).then_some(below)
+    }
+
+    /// Return a short label of the parts and threads of the profile (`p2 t1`, `p1+2`), if any.
+    pub fn parts_label(&self) -> Option<String> {
+        let label = |prefix: &str, numbers: &[u32]| {
+            (!numbers.is_empty()).then(|| format!("{prefix}{}", numbers.iter().join("+")))
+        };
+        let labels = [label("p", &self.parts), label("t", &self.threads)];
+        let label = labels.into_iter().flatten().join(" ");
+        (!label.is_empty()).then_some(label)
+    }
+
+    /// Return the Valgrind version of the creator (`3.18.1` for `callgrind-3.18.1`), if known.
+    pub fn valgrind_version(&self) -> Option<&str> {
+        self.creator.as_deref()?.rsplit_once('-').map(|(_, version)| version)
+    }
+}
+
+/// Which costs were loaded for a run.
+///
+/// Costs that were not loaded are 0.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadedCosts {
+    /// Whether the self (exclusive) costs of the symbols were loaded.
+    pub self_cost: bool,
+    /// Whether the inclusive costs of the symbols were loaded.
+    pub inclusive: bool,
+}
+
+impl LoadedCosts {
+    /// Only self costs were loaded.
+    pub const SELF: Self = Self {
+        self_cost: true,
+        inclusive: false,
+    };
+    /// Only inclusive costs were loaded.
+    pub const INCLUSIVE: Self = Self {
+        self_cost: false,
+        inclusive: true,
+    };
+    /// Both self and inclusive costs were loaded.
+    pub const BOTH: Self = Self {
+        self_cost: true,
+        inclusive: true,
+    };
 }
 
 impl Run {
@@ -30,38 +176,439 @@ impl Run {
         }
     }
 
-    /// Add an IR count for the given symbol in the run.
+    /// Add costs for the given symbol in the run.
+    ///
+    /// `costs` are given in the order of [`Self::events`]. Missing trailing costs are 0. The
+    /// symbol is identified by its name, source file and object together.
     ///
     /// This may be called multiple times with the same symbol. Due to inlining, a symbol may end
-    /// up in different files at different lines. This function _adds_ the IR count each time.
+    /// up in different files at different lines. This function _adds_ the costs each time.
     ///
     /// ```
     /// # use callgrind_differ::runs::Run;
     /// let mut run = Run::new();
-    /// run.add_ir("foo", 12);
-    /// run.add_ir("foo", 24);
-    /// assert_eq!(run.symbols.iter().find(|sym| sym.name == "foo").unwrap().ir, 36);
+    /// run.events = vec!["Ir".to_string()];
+    /// run.add_costs("foo", Some("foo.c"), None, &[12]);
+    /// run.add_costs("foo", Some("foo.c"), None, &[24]);
+    /// run.add_costs("foo", Some("bar.c"), None, &[5]);
+    /// assert_eq!(run.symbols.iter().find(|sym| sym.name == "foo").unwrap().costs, [36]);
+    /// assert_eq!(run.symbols.len(), 2);
     /// ```
-    pub fn add_ir(&mut self, symbol: &str, ir: u64) {
-        if let Some(ref mut symbol) = self.symbols.iter_mut().find(|sym| sym.name == symbol) {
-            symbol.ir += ir;
+    pub fn add_costs(
+        &mut self,
+        symbol: &str,
+        file: Option<&str>,
+        object: Option<&str>,
+        costs: &[i64],
+    ) {
+        let symbol = self.symbol_mut(symbol, file, object);
+        for (total, cost) in symbol.costs.iter_mut().zip(costs) {
+            *total += cost;
+        }
+    }
+
+    /// Add inclusive costs for the given symbol in the run.
+    ///
+    /// This behaves like [`Self::add_costs`], for [`AnnotatedSymbol::inclusive`].
+    pub fn add_inclusive_costs(
+        &mut self,
+        symbol: &str,
+        file: Option<&str>,
+        object: Option<&str>,
+        costs: &[i64],
+    ) {
+        let symbol = self.symbol_mut(symbol, file, object);
+        for (total, cost) in symbol.inclusive.iter_mut().zip(costs) {
+            *total += cost;
+        }
+    }
+
+    /// Return the given symbol, creating it with 0 costs if needed.
+    fn symbol_mut(
+        &mut self,
+        symbol: &str,
+        file: Option<&str>,
+        object: Option<&str>,
+    ) -> &mut AnnotatedSymbol {
+        let n_events = self.events.len();
+        if let Some(i) = self.symbols.iter().position(|sym| {
+            sym.name == symbol && sym.file.as_deref() == file && sym.object.as_deref() == object
+        }) {
+            &mut self.symbols[i]
         } else {
             self.symbols.push(AnnotatedSymbol {
                 name: symbol.to_string(),
-                ir,
+                file: file.map(str::to_string),
+                object: object.map(str::to_string),
+                costs: vec![0; n_events],
+                inclusive: vec![0; n_events],
+                stats: None,
             });
+            self.symbols.last_mut().unwrap()
         }
     }
 
-    /// Load a run from a `callgrind_annotate` output file.
-    pub fn from_callgrind_annotate_file<P: AsRef<Path>>(
-        path: P,
-        replacements: &[StringReplacement],
-    ) -> Result<Self> {
-        Ok(crate::callgrind::parse(
-            BufReader::new(File::open(path)?),
-            replacements,
-        ))
+    /// Aggregate the symbols of the run according to `by`.
+    ///
+    /// The file and object of a symbol are kept only if they are part of what symbols are
+    /// grouped by. Symbols whose file or object is unknown are grouped under `???`, as
+    /// `callgrind_annotate` does.
+    pub fn group_by(&mut self, by: GroupBy) {
+        const UNKNOWN: &str = "???";
+
+        self.regroup(|symbol| {
+            let file = symbol.file.as_deref();
+            let object = symbol.object.as_deref();
+            let (name, file, object) = match by {
+                GroupBy::Function => (symbol.name.as_str(), None, None),
+                GroupBy::File => (file.unwrap_or(UNKNOWN), None, None),
+                GroupBy::Object => (object.unwrap_or(UNKNOWN), None, None),
+                GroupBy::FileFunction => (symbol.name.as_str(), file, None),
+                GroupBy::ObjectFunction => (symbol.name.as_str(), None, object),
+            };
+            (Cow::Borrowed(name), file, object)
+        });
+    }
+
+    /// Return whether the symbols hold what grouping them by `by` needs: the file or the object
+    /// of some of them, if any. Runs loaded from CSV inputs, or from JSON exports of symbols
+    /// grouped by function, do not.
+    pub fn can_group_by(&self, by: GroupBy) -> bool {
+        let known = |symbol: &AnnotatedSymbol| match by {
+            GroupBy::Function => true,
+            GroupBy::File | GroupBy::FileFunction => symbol.file.is_some(),
+            GroupBy::Object | GroupBy::ObjectFunction => symbol.object.is_some(),
+        };
+        self.symbols.is_empty() || self.symbols.iter().any(known)
+    }
+
+    /// Put the symbols that are only an address (`0x0000000000001070`), as in stripped or JIT
+    /// code, into a single `<unresolved in OBJECT>` symbol per object.
+    ///
+    /// Objects are named after their file name (`libc.so.6`), or `???` if unknown. The addresses
+    /// change between builds, while their object and total cost may not.
+    pub fn group_unresolved(&mut self) {
+        const UNKNOWN: &str = "???";
+
+        self.regroup(|symbol| {
+            let file = symbol.file.as_deref();
+            let object = symbol.object.as_deref();
+            let name = if is_address(&symbol.name) {
+                let object_name = object
+                    .and_then(|object| Path::new(object).file_name()?.to_str())
+                    .unwrap_or(UNKNOWN);
+                Cow::Owned(format!("<unresolved in {object_name}>"))
+            } else {
+                Cow::Borrowed(symbol.name.as_str())
+            };
+            (name, file, object)
+        });
+    }
+
+    /// Add up the symbols of the run under the name, file and object that `key` gives each one.
+    ///
+    /// The statistics of a symbol over samples are kept if it is added up alone, and are unknown
+    /// otherwise.
+    fn regroup<F>(&mut self, key: F)
+    where
+        F: for<'a> Fn(&'a AnnotatedSymbol) -> (Cow<'a, str>, Option<&'a str>, Option<&'a str>),
+    {
+        for symbol in std::mem::take(&mut self.symbols) {
+            let (name, file, object) = key(&symbol);
+            let n_symbols = self.symbols.len();
+            self.add_costs(&name, file, object, &symbol.costs);
+            self.add_inclusive_costs(&name, file, object, &symbol.inclusive);
+            let alone = self.symbols.len() > n_symbols;
+            self.symbol_mut(&name, file, object).stats = symbol.stats.clone().filter(|_| alone);
+        }
+    }
+
+    /// Return whether any cost of the run is negative, which only happens for differences.
+    pub fn has_negative_costs(&self) -> bool {
+        self.totals.iter().any(|&total| total < 0)
+            || self.symbols.iter().any(|symbol| {
+                symbol.costs.iter().chain(&symbol.inclusive).any(|&cost| cost < 0)
+            })
+    }
+
+    /// Merge the costs of `other` into `self`, if they are the self and inclusive costs of the
+    /// same profile.
+    ///
+    /// This is the case if one run holds only self costs, the other only inclusive costs, and
+    /// they have the same events and totals. Returns `other` back if they cannot be merged.
+    pub fn merge_costs(&mut self, other: Self) -> Option<Self> {
+        let complementary = (self.loaded == LoadedCosts::SELF
+            && other.loaded == LoadedCosts::INCLUSIVE)
+            || (self.loaded == LoadedCosts::INCLUSIVE && other.loaded == LoadedCosts::SELF);
+        if !complementary
+            || self.kind != other.kind
+            || self.events != other.events
+            || self.totals != other.totals
+        {
+            return Some(other);
+        }
+
+        for symbol in other.symbols {
+            let file = symbol.file.as_deref();
+            let object = symbol.object.as_deref();
+            self.add_costs(&symbol.name, file, object, &symbol.costs);
+            self.add_inclusive_costs(&symbol.name, file, object, &symbol.inclusive);
+        }
+        if self.lines.is_empty() {
+            self.lines = other.lines;
+        }
+        self.loaded = LoadedCosts::BOTH;
+        None
+    }
+
+    /// Merge several listings of the same profile, such as `callgrind_annotate` outputs at
+    /// different thresholds, into a single run named `name`.
+    ///
+    /// The run holds every symbol listed by any of them, with the metadata, annotated source and
+    /// threshold of the most complete one. The totals, and the costs of symbols listed by several
+    /// runs, are expected to be the same: each difference is returned as a conflict, and the costs
+    /// of the most complete run are kept. Runs are told apart by their name in conflicts.
+    ///
+    /// A run holding the inclusive costs of a profile whose self costs are held by the merged run,
+    /// or the other way around, is merged as per [`Self::merge_costs`].
+    ///
+    /// Fails if the runs do not hold the same kind of costs for the same events.
+    pub fn merge_listings(name: String, mut runs: Vec<Self>) -> Result<(Self, Vec<String>)> {
+        // The most complete listing comes first: a complete one, then the lowest threshold.
+        runs.sort_by(|a, b| {
+            let a = a.below_threshold.unwrap_or(0.0);
+            a.total_cmp(&b.below_threshold.unwrap_or(0.0))
+        });
+        let mut runs = runs.into_iter();
+        let Some(mut merged) = runs.next() else {
+            bail!("No input to merge into {name}");
+        };
+
+        let mut conflicts = vec![];
+        // The index of each symbol and the run it was listed by first.
+        let mut known = merged
+            .symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (symbol.key(), (i, merged.name.clone())))
+            .collect::<HashMap<_, _>>();
+        for run in runs {
+            let Some(run) = merged.merge_costs(run) else {
+                continue;
+            };
+            if run.kind != merged.kind || run.events != merged.events || run.loaded != merged.loaded
+            {
+                bail!(
+                    "Cannot merge {} into {name}: it does not hold the same costs and events as {}",
+                    run.name,
+                    merged.name
+                );
+            }
+            let process = |metadata: &RunMetadata| (metadata.command.clone(), metadata.pid);
+            if process(&run.metadata) != process(&merged.metadata)
+                && run.metadata.command.is_some()
+                && merged.metadata.command.is_some()
+            {
+                conflicts.push(format!(
+                    "{name}: {} and {} were not profiled from the same process",
+                    merged.name, run.name
+                ));
+            }
+            if run.totals != merged.totals {
+                conflicts.push(format!(
+                    "{name}: the totals are {} in {} but {} in {}",
+                    format_costs(&merged.events, &merged.totals),
+                    merged.name,
+                    format_costs(&run.events, &run.totals),
+                    run.name
+                ));
+            }
+
+            for symbol in run.symbols {
+                let key = symbol.key();
+                if let Some((i, source)) = known.get(&key) {
+                    let listed = &merged.symbols[*i];
+                    let (costs, listed_costs) = if listed.costs == symbol.costs {
+                        (&symbol.inclusive, &listed.inclusive)
+                    } else {
+                        (&symbol.costs, &listed.costs)
+                    };
+                    if costs != listed_costs {
+                        conflicts.push(format!(
+                            "{name}: `{key}` costs {} in {source} but {} in {}",
+                            format_costs(&run.events, listed_costs),
+                            format_costs(&run.events, costs),
+                            run.name
+                        ));
+                    }
+                } else {
+                    known.insert(key, (merged.symbols.len(), run.name.clone()));
+                    merged.symbols.push(symbol);
+                }
+            }
+            if merged.lines.is_empty() {
+                merged.lines = run.lines;
+            }
+        }
+
+        merged.name = name;
+        Ok((merged, conflicts))
+    }
+
+    /// Add the costs of `other`, another part or thread of the same process, to the costs of
+    /// `self`.
+    ///
+    /// The parts and threads of `other` are added to the metadata. The threshold of the sum is
+    /// the highest one.
+    pub fn add_part(&mut self, other: Self) -> Result<()> {
+        if self.kind != other.kind || self.events != other.events || self.loaded != other.loaded {
+            bail!(
+                "Cannot add {} to {}: it does not hold the same costs and events",
+                other.name,
+                self.name
+            );
+        }
+        for (total, cost) in self.totals.iter_mut().zip(&other.totals) {
+            *total += cost;
+        }
+        for symbol in other.symbols {
+            let file = symbol.file.as_deref();
+            let object = symbol.object.as_deref();
+            self.add_costs(&symbol.name, file, object, &symbol.costs);
+            self.add_inclusive_costs(&symbol.name, file, object, &symbol.inclusive);
+        }
+        for line in other.lines {
+            match self.lines.iter_mut().find(|x| x.file == line.file && x.line == line.line) {
+                Some(x) => x.costs.iter_mut().zip(&line.costs).for_each(|(x, cost)| *x += cost),
+                None => self.lines.push(line),
+            }
+        }
+
+        let union = |a: &[u32], b: &[u32]| a.iter().chain(b).copied().sorted().dedup().collect();
+        self.metadata.parts = union(&self.metadata.parts, &other.metadata.parts);
+        self.metadata.threads = union(&self.metadata.threads, &other.metadata.threads);
+        self.below_threshold = match (self.below_threshold, other.below_threshold) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(())
+    }
+
+    /// Load several samples of the same version, such as repeated runs of a benchmark, as a
+    /// single run named `name` holding their mean costs (see `--sample-group`).
+    ///
+    /// The statistics of the totals, and of the main cost of each symbol (its self cost, or its
+    /// inclusive cost if self costs were not loaded), are kept. A symbol that a sample did not
+    /// list costs 0 in it, unless the sample left out symbols below a threshold: the symbol is
+    /// then left out of the group as well. The run has the metadata of the first sample, without
+    /// its process, and the highest threshold. Annotated source lines are not kept.
+    ///
+    /// Fails if the samples do not hold the same kind of costs for the same events.
+    pub fn from_samples(name: String, samples: &[Self]) -> Result<Self> {
+        let Some(first) = samples.first() else {
+            bail!("No sample in {name}");
+        };
+        let costs = |sample: &Self| (sample.kind, sample.events.clone(), sample.loaded);
+        if let Some(sample) = samples.iter().find(|sample| costs(sample) != costs(first)) {
+            bail!(
+                "Cannot group {} with {} into {name}: they do not hold the same costs and events",
+                sample.name,
+                first.name
+            );
+        }
+
+        let mut group = Self::new_named(name);
+        group.events.clone_from(&first.events);
+        group.loaded = first.loaded;
+        group.kind = first.kind;
+        group.metadata = RunMetadata {
+            data_file: None,
+            pid: None,
+            parts: Vec::new(),
+            threads: Vec::new(),
+            samples: samples.iter().map(|sample| sample.name.clone()).collect(),
+            ..first.metadata.clone()
+        };
+        group.below_threshold = samples
+            .iter()
+            .filter_map(|sample| sample.below_threshold)
+            .max_by(f64::total_cmp);
+        let stats = (0..group.events.len())
+            .map(|event| {
+                let totals = samples.iter().map(|sample| sample.totals[event]).collect_vec();
+                SampleStats::new(&totals).expect("There is a sample at least")
+            })
+            .collect_vec();
+        group.totals = stats.iter().map(SampleStats::rounded_mean).collect();
+        group.totals_stats = Some(stats);
+
+        // The symbols of each sample, and all of them in order of first appearance.
+        let listings = samples
+            .iter()
+            .map(|sample| {
+                let symbols = sample.symbols.iter().map(|symbol| (symbol.key(), symbol));
+                symbols.collect::<HashMap<_, _>>()
+            })
+            .collect_vec();
+        let symbols = samples.iter().flat_map(|sample| &sample.symbols).unique_by(|s| s.key());
+        for symbol in symbols {
+            let key = symbol.key();
+            // The symbol in each sample, or `None` if it costs 0 there.
+            let listed = samples
+                .iter()
+                .zip(&listings)
+                .map(|(sample, listing)| match listing.get(&key) {
+                    Some(symbol) => Some(Some(*symbol)),
+                    None => sample.below_threshold.is_none().then_some(None),
+                })
+                .collect::<Option<Vec<_>>>();
+            let Some(listed) = listed else {
+                continue;
+            };
+            let sample_stats = |costs: fn(&AnnotatedSymbol) -> &Vec<i64>, event: usize| {
+                let costs = listed
+                    .iter()
+                    .map(|symbol| symbol.map_or(0, |symbol| costs(symbol)[event]))
+                    .collect_vec();
+                SampleStats::new(&costs).expect("There is a sample at least")
+            };
+            let events = 0..group.events.len();
+            let costs = events.clone().map(|event| sample_stats(|s| &s.costs, event)).collect_vec();
+            let inclusive = events.map(|event| sample_stats(|s| &s.inclusive, event)).collect_vec();
+            group.symbols.push(AnnotatedSymbol {
+                name: symbol.name.clone(),
+                file: symbol.file.clone(),
+                object: symbol.object.clone(),
+                costs: costs.iter().map(SampleStats::rounded_mean).collect(),
+                inclusive: inclusive.iter().map(SampleStats::rounded_mean).collect(),
+                stats: Some(if group.loaded.self_cost { costs } else { inclusive }),
+            });
+        }
+        Ok(group)
+    }
+
+    /// Load a run from a `callgrind_annotate` output.
+    pub fn from_callgrind_annotate(input: &Input, options: &ParseOptions) -> Result<Self> {
+        Ok(crate::callgrind::parse(input.reader(), &input.path, options)?)
+    }
+
+    /// Load a run from a `cg_annotate` output, as written by Valgrind 3.22 and later.
+    pub fn from_cg_annotate(input: &Input, options: &ParseOptions) -> Result<Self> {
+        Ok(crate::cachegrind::parse(input.reader(), &input.path, options)?)
+    }
+
+    /// Load a run from a raw `cachegrind.out.<pid>` file.
+    ///
+    /// Its format is a subset of the callgrind one, without calls: only self costs are loaded.
+    pub fn from_cachegrind_out(input: &Input, options: &ParseOptions) -> Result<Self> {
+        let mut run = Self::from_callgrind_out(input, options)?;
+        run.loaded = LoadedCosts::SELF;
+        Ok(run)
+    }
+
+    /// Load a run from a raw `callgrind.out.<pid>` file.
+    pub fn from_callgrind_out(input: &Input, options: &ParseOptions) -> Result<Self> {
+        Ok(crate::callgrind_out::parse(input.reader(), &input.path, options)?)
     }
 }
 
@@ -69,17 +616,38 @@ impl Run {
 ///
 /// The annotations do make sense only if they all refer to the same binary (though it may be at
 /// different stages of development).
+///
+/// Costs are stored per event, then per run. An event that a run did not record has unknown costs
+/// and a total of 0 for that run.
 #[derive(Default)]
 pub struct Records {
     /// The names of the runs, if any. This is purely for human readability purposes.
     ///
     /// In case a name is unknown or unset, a blank string is inserted. The length of `run_names`
-    /// must match that of any [`RecordsSymbol`] in [`Self::symbols`].
+    /// must match that of the cost vectors of any [`RecordsSymbol`] in [`Self::symbols`].
     pub run_names: Vec<String>,
-    /// The total IR of each run.
-    pub runs_total_irs: Vec<u64>,
-    /// The symbols and their IR count for each run.
+    /// The events for which costs are stored, in order of first appearance.
+    pub events: Vec<String>,
+    /// The total cost of each run, for each event (`runs_totals[event][run]`).
+    pub runs_totals: Vec<Vec<i64>>,
+    /// Whether each run recorded each event (`runs_recorded[event][run]`).
+    pub runs_recorded: Vec<Vec<bool>>,
+    /// Which costs were loaded for each run.
+    pub runs_loaded: Vec<LoadedCosts>,
+    /// The kind of each run.
+    pub runs_kind: Vec<RunKind>,
+    /// The metadata of each run.
+    pub runs_metadata: Vec<RunMetadata>,
+    /// The percentage of the totals under which symbols may be missing from each run (see
+    /// [`Run::below_threshold`]).
+    pub runs_below_threshold: Vec<Option<f64>>,
+    /// The statistics of the total costs over the samples of each run that is a sample group,
+    /// for each event (`runs_totals_stats[event][run]`), or `None` for other runs.
+    pub runs_totals_stats: Vec<Vec<Option<SampleStats>>>,
+    /// The symbols and their costs for each run.
     pub symbols: Vec<RecordsSymbol>,
+    /// The lines of annotated source and their costs for each run.
+    pub lines: Vec<RecordsLine>,
 }
 
 impl Records {
@@ -88,64 +656,325 @@ impl Records {
         Records::default()
     }
 
+    /// Return the index of the given event, if it is stored in `Self`.
+    pub fn event_index(&self, event: &str) -> Option<usize> {
+        self.events.iter().position(|e| e == event)
+    }
+
+    /// Return the index of the given event, adding it as not recorded by any run if needed.
+    fn event_index_or_insert(&mut self, event: &str) -> usize {
+        if let Some(index) = self.event_index(event) {
+            return index;
+        }
+        let n_runs = self.n_runs();
+        self.events.push(event.to_string());
+        self.runs_totals.push(vec![0; n_runs]);
+        self.runs_recorded.push(vec![false; n_runs]);
+        self.runs_totals_stats.push(vec![None; n_runs]);
+        for symbol in &mut self.symbols {
+            symbol.costs.push(vec![None; n_runs]);
+            symbol.inclusive.push(vec![None; n_runs]);
+            symbol.stats.push(vec![None; n_runs]);
+        }
+        for line in &mut self.lines {
+            line.costs.push(vec![0; n_runs]);
+        }
+        self.events.len() - 1
+    }
+
+    /// Return the indices of the runs that are profiles, as opposed to differences.
+    pub fn profile_runs(&self) -> impl DoubleEndedIterator<Item = usize> + '_ {
+        self.runs_kind
+            .iter()
+            .enumerate()
+            .filter(|(_, &kind)| kind == RunKind::Profile)
+            .map(|(i, _)| i)
+    }
+
+    /// Return a description of each way in which the profile runs were not produced alike: from
+    /// different targets, event sets, thresholds or Valgrind versions.
+    ///
+    /// Runs that do not tell are not taken into account.
+    pub fn metadata_mismatches(&self) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        let mut check = |what: &str, value: &dyn Fn(&RunMetadata) -> Option<String>| {
+            let values = self
+                .profile_runs()
+                .filter_map(|run| Some((value(&self.runs_metadata[run])?, run)))
+                .into_group_map();
+            if values.len() > 1 {
+                let values = values
+                    .into_iter()
+                    .sorted_by_key(|(_, runs)| runs[0])
+                    .map(|(value, runs)| {
+                        let runs = runs.iter().map(|&run| &self.run_names[run]).join(", ");
+                        format!("`{value}` ({runs})")
+                    })
+                    .join(", ");
+                mismatches.push(format!("Runs have different {what}: {values}"));
+            }
+        };
+        check("targets", &|metadata| metadata.command.clone());
+        check("recorded events", &|metadata| {
+            Some(metadata.events_recorded.join(" ")).filter(|events| !events.is_empty())
+        });
+        check("thresholds", &|metadata| metadata.thresholds.clone());
+        check("Valgrind versions", &|metadata| {
+            metadata.valgrind_version().map(str::to_string)
+        });
+        mismatches
+    }
+
+    /// Return the total cost of each run for an event, unknown for the runs that did not record it.
+    pub fn known_totals(&self, event: usize) -> Vec<Option<i64>> {
+        let recorded = &self.runs_recorded[event];
+        let totals = self.runs_totals[event].iter().zip(recorded);
+        totals.map(|(&total, &recorded)| recorded.then_some(total)).collect()
+    }
+
+    /// Return whether every run holds self costs.
+    pub fn has_self_costs(&self) -> bool {
+        self.runs_loaded.iter().all(|loaded| loaded.self_cost)
+    }
+
+    /// Return whether every run holds inclusive costs.
+    pub fn has_inclusive_costs(&self) -> bool {
+        self.runs_loaded.iter().all(|loaded| loaded.inclusive)
+    }
+
+    /// Return the cost for an event of a symbol that the run did not list: unknown if the run did
+    /// not record the event or left out symbols below a threshold, 0 otherwise.
+    fn missing_cost(&self, event: usize, run: usize) -> Option<i64> {
+        match (self.runs_recorded[event][run], self.runs_below_threshold[run]) {
+            (true, None) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Return the statistics for an event of a symbol that no sample of a sample group listed:
+    /// all its samples cost 0, unless their cost is unknown (see [`Self::missing_cost`]).
+    fn missing_stats(&self, event: usize, run: usize) -> Option<SampleStats> {
+        let samples = self.runs_metadata[run].samples.len();
+        if samples == 0 || self.missing_cost(event, run).is_none() {
+            return None;
+        }
+        SampleStats::new(&vec![0; samples])
+    }
+
+    /// Return how a cost left out of the listing of a run is shown (`<1%`), if it may be.
+    pub fn below_threshold_label(&self, run: usize) -> Option<String> {
+        let below = self.runs_below_threshold[run]?;
+        // Thresholds are percentages with few decimals, `100 - 99.99` is not quite `0.01`.
+        let below = format!("{below:.3}");
+        Some(format!("<{}%", below.trim_end_matches('0').trim_end_matches('.')))
+    }
+
+    /// Return how a cost is written in a CSV export: its value, or the label of its threshold if
+    /// it is unknown.
+    fn csv_cost(&self, run: usize, cost: Option<i64>) -> String {
+        match cost {
+            Some(cost) => cost.to_string(),
+            None => self.below_threshold_label(run).unwrap_or_default(),
+        }
+    }
+
+    /// Return the costs of the symbol used for sorting and exporting: its self costs, or its
+    /// inclusive costs if some run lacks self costs.
+    pub fn main_costs<'a>(&self, symbol: &'a RecordsSymbol) -> &'a [Vec<Option<i64>>] {
+        if self.has_self_costs() {
+            &symbol.costs
+        } else {
+            &symbol.inclusive
+        }
+    }
+
     /// Add annotations about a run to the records.
     pub fn add_run(&mut self, run: Run) {
         self.assert_invariants();
 
+        // Where each of the run's events is stored in `self`.
+        let event_indices = run
+            .events
+            .iter()
+            .map(|event| self.event_index_or_insert(event))
+            .collect::<Vec<_>>();
+
+        // The costs of previous runs for symbols they did not list, for each event.
+        let missing_costs = (0..self.events.len())
+            .map(|event| (0..self.n_runs()).map(|run| self.missing_cost(event, run)).collect())
+            .collect::<Vec<Vec<_>>>();
+        let missing_stats = (0..self.events.len())
+            .map(|event| (0..self.n_runs()).map(|run| self.missing_stats(event, run)).collect())
+            .collect::<Vec<Vec<_>>>();
+
+        // Symbols are matched across runs by the name they are shown with.
+        let mut symbol_indices = self
+            .symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (symbol.key(), i))
+            .collect::<HashMap<_, _>>();
+
         for run_symbol in run.symbols {
-            // Add an `irs` entry for each symbol.
-            if let Some(ref mut symbol) = self
-                .symbols
-                .iter_mut()
-                .find(|symbol| symbol.name == run_symbol.name)
-            {
-                symbol.irs.push(run_symbol.ir);
+            // Find the symbol, or create it. When creating it, we must already push
+            // `self.n_runs()` missing costs into it to account for previous runs.
+            let key = run_symbol.key();
+            let symbol = if let Some(&i) = symbol_indices.get(&key) {
+                &mut self.symbols[i]
             } else {
-                // If we can't find the symbol, we have to create it. However, we must already push
-                // `self.n_runs()` zeroes into it to account for previous runs.
-                let mut new_symbol = RecordsSymbol {
+                symbol_indices.insert(key, self.symbols.len());
+                self.symbols.push(RecordsSymbol {
                     name: run_symbol.name,
-                    irs: vec![0; self.n_runs()],
-                };
-                new_symbol.irs.push(run_symbol.ir);
-                self.symbols.push(new_symbol);
+                    file: run_symbol.file,
+                    object: run_symbol.object,
+                    costs: missing_costs.clone(),
+                    inclusive: missing_costs.clone(),
+                    renamed_from: Vec::new(),
+                    stats: missing_stats.clone(),
+                });
+                self.symbols.last_mut().unwrap()
+            };
+
+            // Add a cost entry for each of the run's events.
+            for (&event, &cost) in event_indices.iter().zip(&run_symbol.costs) {
+                symbol.costs[event].push(Some(cost));
+            }
+            for (&event, &cost) in event_indices.iter().zip(&run_symbol.inclusive) {
+                symbol.inclusive[event].push(Some(cost));
+            }
+            for (&event, &stats) in event_indices.iter().zip(run_symbol.stats.iter().flatten()) {
+                symbol.stats[event].push(Some(stats));
             }
         }
 
+        // Lines are matched across runs by their file and number.
+        let mut line_indices = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| ((line.file.clone(), line.line), i))
+            .collect::<HashMap<_, _>>();
+
+        for run_line in run.lines {
+            let n_runs = self.n_runs();
+            let n_events = self.events.len();
+            let key = (run_line.file, run_line.line);
+            let line = if let Some(&i) = line_indices.get(&key) {
+                &mut self.lines[i]
+            } else {
+                line_indices.insert(key.clone(), self.lines.len());
+                self.lines.push(RecordsLine {
+                    file: key.0,
+                    line: key.1,
+                    text: run_line.text,
+                    costs: vec![vec![0; n_runs]; n_events],
+                });
+                self.lines.last_mut().unwrap()
+            };
+            for (&event, &cost) in event_indices.iter().zip(&run_line.costs) {
+                line.costs[event].push(cost);
+            }
+        }
+
+        for (&event, &total) in event_indices.iter().zip(&run.totals) {
+            self.runs_totals[event].push(total);
+            self.runs_recorded[event].push(true);
+        }
+        for (&event, &stats) in event_indices.iter().zip(run.totals_stats.iter().flatten()) {
+            self.runs_totals_stats[event].push(Some(stats));
+        }
+
         // Push the name of the run, this will update [`Self::n_runs`].
         self.run_names.push(run.name);
-        self.runs_total_irs.push(run.total_ir);
+        self.runs_loaded.push(run.loaded);
+        self.runs_kind.push(run.kind);
+        self.runs_metadata.push(run.metadata);
+        self.runs_below_threshold.push(run.below_threshold);
+
+        self.fill_missing_costs();
 
+        // As long as the invariants were held before, they should hold now.
+        self.assert_invariants();
+    }
+
+    /// Add a 0 total to each event the last run did not record, and a missing cost to each symbol
+    /// that was not hit by the run (see [`Self::missing_cost`]).
+    fn fill_missing_costs(&mut self) {
         let n_runs = self.n_runs();
-        // Add a 0 to each symbol that was not hit by the run.
+        for (totals, recorded) in self.runs_totals.iter_mut().zip(&mut self.runs_recorded) {
+            if totals.len() != n_runs {
+                totals.push(0);
+                recorded.push(false);
+            }
+        }
+        for stats in &mut self.runs_totals_stats {
+            if stats.len() != n_runs {
+                stats.push(None);
+            }
+        }
+        let missing_costs = (0..self.events.len())
+            .map(|event| self.missing_cost(event, n_runs - 1))
+            .collect::<Vec<_>>();
+        let missing_stats = (0..self.events.len())
+            .map(|event| self.missing_stats(event, n_runs - 1))
+            .collect::<Vec<_>>();
         for ref mut symbol in &mut self.symbols {
-            if symbol.irs.len() != n_runs {
-                symbol.irs.push(0);
+            for costs in [&mut symbol.costs, &mut symbol.inclusive] {
+                for (costs, &missing_cost) in costs.iter_mut().zip(&missing_costs) {
+                    if costs.len() != n_runs {
+                        costs.push(missing_cost);
+                    }
+                }
+            }
+            for (stats, &missing_stats) in symbol.stats.iter_mut().zip(&missing_stats) {
+                if stats.len() != n_runs {
+                    stats.push(missing_stats);
+                }
+            }
+        }
+        for line in &mut self.lines {
+            for costs in &mut line.costs {
+                if costs.len() != n_runs {
+                    costs.push(0);
+                }
             }
         }
-
-        // As long as the invariants were held before, they should hold now.
-        self.assert_invariants();
     }
 
-    /// Sort the symbols according to the given order.
+    /// Sort the symbols according to the given order, using the costs of the given event.
     ///
-    /// See [`SortBy`] for more details.
-    pub fn sort(&mut self, by: SortBy) -> Result<()> {
+    /// Self costs are used, unless some run lacks them, in which case inclusive costs are used.
+    /// Symbols whose cost is unknown (below the threshold of the run) come last, whatever the
+    /// order. See [`SortBy`] for more details.
+    pub fn sort(&mut self, by: SortBy, event: usize) -> Result<()> {
         let n = self.n_runs();
-        match by.field {
-            SortByField::Symbol => self.symbols.sort_by(|a, b| a.name.cmp(&b.name)),
-            SortByField::FirstIR => self.symbols.sort_by(|a, b| a.irs[0].cmp(&b.irs[0])),
-            SortByField::LastIR => self.symbols.sort_by(|a, b| a.irs[n - 1].cmp(&b.irs[n - 1])),
-            SortByField::ColumnIR(x) if (x as usize) < n => self
-                .symbols
-                .sort_by(|a, b| a.irs[x as usize].cmp(&b.irs[x as usize])),
+        let inclusive = !self.has_self_costs();
+        let cost = move |symbol: &RecordsSymbol, x: usize| {
+            if inclusive {
+                symbol.inclusive[event][x]
+            } else {
+                symbol.costs[event][x]
+            }
+        };
+        let column = match by.field {
+            SortByField::Symbol => None,
+            SortByField::FirstIR => Some(0),
+            SortByField::LastIR => Some(n - 1),
+            SortByField::ColumnIR(x) if (x as usize) < n => Some(x as usize),
             SortByField::ColumnIR(x) => bail!("Invalid column {x} (got {n} columns)"),
+        };
+        match column {
+            None => self.symbols.sort_by_cached_key(RecordsSymbol::key),
+            Some(x) => self.symbols.sort_by_key(|symbol| cost(symbol, x)),
         }
 
         if matches!(by.order, SortByOrder::Descending) {
             self.symbols.reverse();
         }
+        if let Some(x) = column {
+            // The sort is stable, this only moves unknown costs to the end.
+            self.symbols.sort_by_key(|symbol| cost(symbol, x).is_none());
+        }
 
         Ok(())
     }
@@ -155,6 +984,47 @@ impl Records {
         self.run_names.len()
     }
 
+    /// Return the given run, as loaded from a file holding several runs (CSV or JSON).
+    ///
+    /// Only the events the run recorded are kept. Symbols with unknown or 0 costs are left out,
+    /// as in the profile they come from. Annotated source lines are not kept.
+    pub fn run(&self, run: usize) -> Run {
+        let events = (0..self.events.len())
+            .filter(|&event| self.runs_recorded[event][run])
+            .collect::<Vec<_>>();
+        let mut result = Run::new_named(self.run_names[run].clone());
+        result.events = events.iter().map(|&event| self.events[event].clone()).collect();
+        result.loaded = self.runs_loaded[run];
+        result.kind = self.runs_kind[run];
+        result.metadata = self.runs_metadata[run].clone();
+        result.totals = run_values(&self.runs_totals, &events, run).collect();
+        result.below_threshold = self.runs_below_threshold[run];
+        result.totals_stats = run_values(&self.runs_totals_stats, &events, run).collect();
+
+        for symbol in &self.symbols {
+            let costs = run_values(&symbol.costs, &events, run).collect::<Vec<_>>();
+            let inclusive = run_values(&symbol.inclusive, &events, run).collect::<Vec<_>>();
+            let listed = |costs: &[Option<i64>]| {
+                costs.iter().any(|&cost| cost.is_some_and(|cost| cost != 0))
+            };
+            if !(listed(&costs) || result.loaded.inclusive && listed(&inclusive)) {
+                continue;
+            }
+            let known = |costs: Vec<Option<i64>>| {
+                costs.into_iter().map(|cost| cost.unwrap_or(0)).collect::<Vec<_>>()
+            };
+            let (name, file, object) =
+                (&symbol.name, symbol.file.as_deref(), symbol.object.as_deref());
+            result.add_costs(name, file, object, &known(costs));
+            if result.loaded.inclusive {
+                result.add_inclusive_costs(name, file, object, &known(inclusive));
+            }
+            result.symbol_mut(name, file, object).stats =
+                run_values(&symbol.stats, &events, run).collect();
+        }
+        result
+    }
+
     /// Make sure that the invariants of the structure are held.
     ///
     /// This function functionally does nothing, but checking integrity is cheap and may save time
@@ -164,45 +1034,152 @@ impl Records {
     /// This function panics if an invariant is broken.
     pub fn assert_invariants(&self) {
         let n_runs = self.n_runs();
+        let n_events = self.events.len();
 
-        // The number of runs contained in `self.run_names` must match that of
-        // `self.runs_total_irs`.
         assert!(
-            n_runs == self.runs_total_irs.len(),
-            "Invalid # of total irs (got {}, expected{n_runs})",
-            self.runs_total_irs.len()
+            n_runs == self.runs_loaded.len()
+                && n_runs == self.runs_kind.len()
+                && n_runs == self.runs_metadata.len()
+                && n_runs == self.runs_below_threshold.len(),
+            "Invalid # of loaded costs, kinds, metadata or thresholds (got {}, {}, {} and {}, \
+             expected {n_runs})",
+            self.runs_loaded.len(),
+            self.runs_kind.len(),
+            self.runs_metadata.len(),
+            self.runs_below_threshold.len()
         );
 
-        // The number of runs contained in `self.run_names` must match that of each symbol in
-        // `self.symbols`.
+        // The number of events contained in `self.events` must match that of
+        // `self.runs_totals`, and the number of runs that of each of its entries.
+        assert!(
+            n_events == self.runs_totals.len(),
+            "Invalid # of events for totals (got {}, expected {n_events})",
+            self.runs_totals.len()
+        );
+        for totals in &self.runs_totals {
+            assert!(
+                n_runs == totals.len(),
+                "Invalid # of totals (got {}, expected {n_runs})",
+                totals.len()
+            );
+        }
+        assert!(
+            n_events == self.runs_totals_stats.len()
+                && self.runs_totals_stats.iter().all(|stats| stats.len() == n_runs),
+            "Invalid # of events or runs for the statistics of the totals"
+        );
+        assert!(
+            n_events == self.runs_recorded.len()
+                && self.runs_recorded.iter().all(|recorded| recorded.len() == n_runs),
+            "Invalid # of events or runs for the recorded events"
+        );
+
+        // The same goes for the costs of each symbol in `self.symbols`.
         for symbol in &self.symbols {
             assert!(
-                symbol.irs.len() == n_runs,
-                "Invalid # of runs for symbol {} (got {}, expected {n_runs})",
+                symbol.costs.len() == n_events && symbol.inclusive.len() == n_events,
+                "Invalid # of events for symbol {} (got {} and {}, expected {n_events})",
                 symbol.name,
-                symbol.irs.len()
+                symbol.costs.len(),
+                symbol.inclusive.len()
+            );
+            for costs in symbol.costs.iter().chain(&symbol.inclusive) {
+                assert!(
+                    costs.len() == n_runs,
+                    "Invalid # of runs for symbol {} (got {}, expected {n_runs})",
+                    symbol.name,
+                    costs.len()
+                );
+            }
+            assert!(
+                symbol.stats.len() == n_events
+                    && symbol.stats.iter().all(|stats| stats.len() == n_runs),
+                "Invalid # of events or runs for the statistics of symbol {}",
+                symbol.name
             );
         }
+
+        // And for the costs of each line in `self.lines`.
+        for line in &self.lines {
+            assert!(
+                line.costs.len() == n_events,
+                "Invalid # of events for line {}:{} (got {}, expected {n_events})",
+                line.file,
+                line.line,
+                line.costs.len()
+            );
+            for costs in &line.costs {
+                assert!(
+                    costs.len() == n_runs,
+                    "Invalid # of runs for line {}:{} (got {}, expected {n_runs})",
+                    line.file,
+                    line.line,
+                    costs.len()
+                );
+            }
+        }
+    }
+
+    /// Return the lines of annotated source of the given file, sorted by line number.
+    ///
+    /// The file may be given by its full path, as annotated, or by a suffix of it (`main.c` for
+    /// `src/main.c`). Returns `None` if no annotated file matches.
+    pub fn source_lines(&self, file: &str) -> Option<Vec<&RecordsLine>> {
+        let matches = |annotated: &str| {
+            annotated == file
+                || annotated
+                    .strip_suffix(file)
+                    .is_some_and(|prefix| prefix.ends_with('/'))
+        };
+        let mut lines = self
+            .lines
+            .iter()
+            .filter(|line| matches(&line.file))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return None;
+        }
+        lines.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        Some(lines)
     }
 
-    /// Load records from a CSV file.
+    /// Load records from a CSV input.
     ///
     /// The CSV file should have the following format:
     /// - First column: symbol names
     /// - Subsequent columns: IR counts for each run
     /// - Optional header row (detected automatically)
-    pub fn from_csv_file<P: AsRef<Path>>(
-        path: P,
-        replacements: &[StringReplacement],
-    ) -> Result<Self> {
-        let file = File::open(path)?;
+    ///
+    /// If every column of the header is of the form `<run>:<event>` (as written by
+    /// [`Self::to_csv_file`] when exporting several events), columns are grouped into runs and
+    /// events accordingly. Otherwise, each column is a run and its cells are `Ir` counts.
+    ///
+    /// Cells such as `<1%` are costs below the threshold of their run, as exported by
+    /// [`Self::to_csv_file`]. Empty cells are the costs of an event their run did not record.
+    ///
+    /// The output of [`Self::to_csv_file_enhanced`] is recognized by its header: only its `_ir`
+    /// columns are loaded, as runs named without the suffix.
+    ///
+    /// A `PROGRAM TOTALS` row, as written by both exports, gives the total costs of the runs.
+    /// Without it, the totals are the sums of the symbol costs.
+    ///
+    /// A cell that is not a count fails the parse. With [`ParseOptions::lenient`], its row is
+    /// skipped with a warning instead.
+    pub fn from_csv(input: &Input, options: &ParseOptions) -> Result<Self> {
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(false)
-            .from_reader(file);
+            .from_reader(input.reader());
 
         let mut records = Self::new();
         let mut first_row = true;
         let mut column_names: Vec<String> = Vec::new();
+        // Whether each column is loaded, which is all of them but the differences and
+        // percentages of an enhanced export.
+        let mut loaded_columns: Vec<bool> = Vec::new();
+        // The `(event, run)` indices each column maps to, if it is loaded.
+        let mut columns: Vec<Option<(usize, usize)>> = Vec::new();
+        // The costs of the `PROGRAM TOTALS` row, if any.
+        let mut totals_row = None;
 
         for result in reader.records() {
             let record = result?;
@@ -219,6 +1196,10 @@ impl Records {
                 for i in 1..record.len() {
                     column_names.push(record.get(i).unwrap_or(&format!("Run {i}")).to_string());
                 }
+                if let Some(runs) = enhanced_csv_runs(&column_names) {
+                    loaded_columns = runs.iter().map(Option::is_some).collect();
+                    column_names = runs.into_iter().flatten().collect();
+                }
                 first_row = false;
                 continue;
             }
@@ -233,59 +1214,178 @@ impl Records {
 
             // Initialize runs if this is the first data row
             if records.n_runs() == 0 {
-                records.run_names.clone_from(&column_names);
-                records.runs_total_irs = vec![0; column_names.len()];
+                let mut loaded = records.init_csv_columns(&column_names).into_iter();
+                columns = (0..record.len() - 1)
+                    .map(|i| match loaded_columns.get(i) {
+                        Some(false) => None,
+                        _ => loaded.next(),
+                    })
+                    .collect();
             }
 
-            // Apply string replacements to symbol name
-            let processed_symbol_name = replacements.iter().fold(
-                std::borrow::Cow::Borrowed(symbol_name.as_str()),
-                |name, replacement| replacement.perform(name)
-            );
-
+            let is_totals = symbol_name == CSV_TOTALS;
             let mut symbol = RecordsSymbol {
-                name: processed_symbol_name.to_string(),
-                irs: Vec::new(),
+                name: options.rewrite_symbol(symbol_name),
+                file: None,
+                object: None,
+                costs: vec![vec![None; records.n_runs()]; records.events.len()],
+                inclusive: vec![vec![None; records.n_runs()]; records.events.len()],
+                renamed_from: Vec::new(),
+                stats: vec![vec![None; records.n_runs()]; records.events.len()],
             };
 
             // Parse IR counts for each run
-            for i in 1..record.len().min(column_names.len() + 1) {
-                if let Some(ir_str) = record.get(i) {
-                    let ir = ir_str.trim().parse::<u64>().unwrap_or(0);
-                    symbol.irs.push(ir);
-                    records.runs_total_irs[i - 1] += ir;
-                } else {
-                    symbol.irs.push(0);
+            let cells = match read_csv_costs(&record, &columns) {
+                Ok(cells) => cells,
+                Err(ir_str) => {
+                    let line = record.position().map_or(0, |position| position.line() as usize);
+                    let error = ParseError::new(
+                        &input.path,
+                        line,
+                        ParseErrorKind::InvalidCount(ir_str.to_string()),
+                    );
+                    if !options.lenient {
+                        return Err(error.into());
+                    }
+                    warn_skipped(&error);
+                    continue;
                 }
-            }
+            };
 
-            // Pad with zeros if needed
-            while symbol.irs.len() < records.n_runs() {
-                symbol.irs.push(0);
+            for (event, run, cost) in cells {
+                records.runs_recorded[event][run] = true;
+                match cost {
+                    CsvCost::Count(cost) => symbol.costs[event][run] = Some(cost),
+                    CsvCost::BelowThreshold(below) if !is_totals => {
+                        records.runs_below_threshold[run] = Some(below);
+                    }
+                    CsvCost::BelowThreshold(_) | CsvCost::Empty => {}
+                }
+            }
+            if is_totals {
+                totals_row = Some(symbol.costs);
+                continue;
             }
-
             records.symbols.push(symbol);
         }
 
+        records.set_csv_totals(totals_row.as_deref());
+        records.runs_totals_stats = vec![vec![None; records.n_runs()]; records.events.len()];
+
         records.assert_invariants();
         Ok(records)
     }
 
+    /// Set the totals of the runs loaded from a CSV file, from its `PROGRAM TOTALS` row if any.
+    ///
+    /// The totals row holds the real totals, which the listed symbols may not add up to. Totals
+    /// missing from it are the sums of the known symbol costs.
+    fn set_csv_totals(&mut self, totals_row: Option<&[Vec<Option<i64>>]>) {
+        for (event, totals) in self.runs_totals.iter_mut().enumerate() {
+            for (run, total) in totals.iter_mut().enumerate() {
+                *total = totals_row
+                    .and_then(|row| row[event][run])
+                    .unwrap_or_else(|| {
+                        self.symbols.iter().filter_map(|symbol| symbol.costs[event][run]).sum()
+                    });
+            }
+        }
+    }
+
+    /// Set up the runs and events of empty records from the columns of a CSV file.
+    ///
+    /// Returns the `(event, run)` indices each column maps to.
+    fn init_csv_columns(&mut self, column_names: &[String]) -> Vec<(usize, usize)> {
+        let split_columns = column_names
+            .iter()
+            .map(|column| {
+                column.rsplit_once(':').filter(|(run, event)| {
+                    !run.is_empty()
+                        && !event.is_empty()
+                        && event.chars().all(|c| c.is_ascii_alphanumeric())
+                })
+            })
+            .collect::<Option<Vec<_>>>();
+
+        if let Some(split_columns) = split_columns {
+            split_columns
+                .into_iter()
+                .map(|(run, event)| {
+                    let run = if let Some(i) = self.run_names.iter().position(|r| r == run) {
+                        i
+                    } else {
+                        self.run_names.push(run.to_string());
+                        self.runs_loaded.push(LoadedCosts::SELF);
+                        self.runs_kind.push(RunKind::Profile);
+                        self.runs_metadata.push(RunMetadata::default());
+                        self.runs_below_threshold.push(None);
+                        for totals in &mut self.runs_totals {
+                            totals.push(0);
+                        }
+                        for recorded in &mut self.runs_recorded {
+                            recorded.push(false);
+                        }
+                        self.run_names.len() - 1
+                    };
+                    (self.event_index_or_insert(event), run)
+                })
+                .collect()
+        } else {
+            self.run_names = column_names.to_vec();
+            self.runs_loaded = vec![LoadedCosts::SELF; column_names.len()];
+            self.runs_kind = vec![RunKind::Profile; column_names.len()];
+            self.runs_metadata = vec![RunMetadata::default(); column_names.len()];
+            self.runs_below_threshold = vec![None; column_names.len()];
+            let event = self.event_index_or_insert("Ir");
+            (0..column_names.len()).map(|run| (event, run)).collect()
+        }
+    }
+
+    /// Return the total costs of the runs, in the layout of the costs of a symbol, for the
+    /// totals row of the CSV exports.
+    fn csv_totals(&self) -> Vec<Vec<Option<i64>>> {
+        (0..self.events.len()).map(|event| self.known_totals(event)).collect()
+    }
+
+    /// Return the name of the column holding the costs of the given run and event.
+    ///
+    /// When a single event is exported, columns are named after their run only.
+    fn csv_column_name(&self, run: usize, event: usize, events: &[usize]) -> String {
+        if events.len() == 1 {
+            self.run_names[run].clone()
+        } else {
+            format!("{}:{}", self.run_names[run], self.events[event])
+        }
+    }
+
     /// Export records to a CSV file.
-    pub fn to_csv_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+    ///
+    /// Only the costs of the given events are exported. Self costs are exported, unless some run
+    /// lacks them, in which case inclusive costs are exported. The first row after the header is
+    /// the `PROGRAM TOTALS` of the runs.
+    pub fn to_csv_file<P: AsRef<Path>>(&self, path: P, events: &[usize]) -> Result<()> {
         let file = File::create(path)?;
         let mut writer = csv::Writer::from_writer(file);
 
         // Write header
         let mut header = vec!["name".to_string()];
-        header.extend(self.run_names.iter().cloned());
+        for &event in events {
+            for run in 0..self.n_runs() {
+                header.push(self.csv_column_name(run, event, events));
+            }
+        }
         writer.write_record(&header)?;
 
-        // Write symbol data
-        for symbol in &self.symbols {
-            let mut record = vec![symbol.name.clone()];
-            for ir in &symbol.irs {
-                record.push(ir.to_string());
+        // Write the totals, then symbol data
+        let totals = self.csv_totals();
+        let rows = iter::once((CSV_TOTALS.to_string(), totals.as_slice()))
+            .chain(self.symbols.iter().map(|symbol| (symbol.key(), self.main_costs(symbol))));
+        for (name, costs) in rows {
+            let mut record = vec![name];
+            for &event in events {
+                for (run, &ir) in costs[event].iter().enumerate() {
+                    record.push(self.csv_cost(run, ir));
+                }
             }
             writer.write_record(&record)?;
         }
@@ -295,6 +1395,12 @@ impl Records {
     }
     
     /// Export records to a CSV file with enhanced options including percentages and differences.
+    ///
+    /// Delta runs have no difference nor percentage columns. Differences and percentages are left
+    /// empty when either cost is unknown (below the threshold of its run). The first row after the
+    /// header is the `PROGRAM TOTALS` of the runs.
+    ///
+    /// Only the costs of the given events are exported.
     pub fn to_csv_file_enhanced<P: AsRef<Path>>(
         &self,
         path: P,
@@ -302,87 +1408,93 @@ impl Records {
         include_differences: bool,
         include_all_data: bool,
         reference_column: usize,
+        events: &[usize],
     ) -> Result<()> {
         let file = File::create(path)?;
         let mut writer = csv::Writer::from_writer(file);
 
+        // Delta runs are differences already, they are not compared to the reference.
+        let compared = |i: usize| i != reference_column && self.runs_kind[i] == RunKind::Profile;
+
         // Build header based on options
         let mut header = vec!["name".to_string()];
         
-        if include_all_data {
-            // Include everything: IR, differences, and percentages
-            for (i, run_name) in self.run_names.iter().enumerate() {
-                if i == reference_column {
-                    header.push(format!("{run_name}_ir"));
-                } else {
+        for &event in events {
+            if include_all_data {
+                // Include everything: IR, differences, and percentages
+                for i in 0..self.n_runs() {
+                    let run_name = self.csv_column_name(i, event, events);
                     header.push(format!("{run_name}_ir"));
-                    header.push(format!("{run_name}_diff"));
-                    header.push(format!("{run_name}_pct"));
-                }
-            }
-        } else {
-            // Selective inclusion
-            for (i, run_name) in self.run_names.iter().enumerate() {
-                header.push(format!("{run_name}_ir"));
-                if i != reference_column && include_differences {
-                    header.push(format!("{run_name}_diff"));
+                    if compared(i) {
+                        header.push(format!("{run_name}_diff"));
+                        header.push(format!("{run_name}_pct"));
+                    }
                 }
-                if i != reference_column && include_percentages {
-                    header.push(format!("{run_name}_pct"));
+            } else {
+                // Selective inclusion
+                for i in 0..self.n_runs() {
+                    let run_name = self.csv_column_name(i, event, events);
+                    header.push(format!("{run_name}_ir"));
+                    if compared(i) && include_differences {
+                        header.push(format!("{run_name}_diff"));
+                    }
+                    if compared(i) && include_percentages {
+                        header.push(format!("{run_name}_pct"));
+                    }
                 }
             }
         }
         
         writer.write_record(&header)?;
 
-        // Write symbol data with calculations
-        for symbol in &self.symbols {
-            let mut record = vec![symbol.name.clone()];
-            
-            let reference_ir = if reference_column < symbol.irs.len() {
-                symbol.irs[reference_column]
-            } else {
-                0
-            };
-            
-            if include_all_data {
-                for (i, &ir) in symbol.irs.iter().enumerate() {
-                    record.push(ir.to_string());
-                    
-                    if i != reference_column {
-                        // Calculate difference
-                        #[allow(clippy::cast_possible_wrap)]
-                        let diff = (ir as i64) - (reference_ir as i64);
-                        record.push(diff.to_string());
-                        
-                        // Calculate percentage
-                        let percentage = if reference_ir == 0 {
-                            if ir == 0 { 0.0 } else { 100.0 }
-                        } else {
-                            ((ir as f64 - reference_ir as f64) / reference_ir as f64) * 100.0
-                        };
-                        record.push(format!("{percentage:.3}"));
-                    }
-                }
-            } else {
-                // Selective data inclusion
-                for (i, &ir) in symbol.irs.iter().enumerate() {
-                    record.push(ir.to_string());
-                    
-                    if i != reference_column {
-                        if include_differences {
-                            #[allow(clippy::cast_possible_wrap)]
-                            let diff = (ir as i64) - (reference_ir as i64);
-                            record.push(diff.to_string());
+        // Write the totals, then symbol data with calculations
+        let totals = self.csv_totals();
+        let rows = iter::once((CSV_TOTALS.to_string(), totals.as_slice()))
+            .chain(self.symbols.iter().map(|symbol| (symbol.key(), self.main_costs(symbol))));
+        for (name, costs) in rows {
+            let mut record = vec![name];
+
+            for &event in events {
+                let irs = &costs[event];
+                let reference_ir = if reference_column < irs.len() {
+                    irs[reference_column]
+                } else {
+                    Some(0)
+                };
+                let diff = |ir: Option<i64>| Some(ir? - reference_ir?).map(|diff| diff.to_string());
+                let percentage = |ir: Option<i64>| {
+                    let (ir, reference_ir) = (ir?, reference_ir?);
+                    let percentage = if reference_ir == 0 {
+                        if ir == 0 { 0.0 } else { 100.0 }
+                    } else {
+                        ((ir as f64 - reference_ir as f64) / reference_ir as f64) * 100.0
+                    };
+                    Some(format!("{percentage:.3}"))
+                };
+
+                if include_all_data {
+                    for (i, &ir) in irs.iter().enumerate() {
+                        record.push(self.csv_cost(i, ir));
+
+                        if compared(i) {
+                            // Calculate difference and percentage
+                            record.push(diff(ir).unwrap_or_default());
+                            record.push(percentage(ir).unwrap_or_default());
                         }
-                        
-                        if include_percentages {
-                            let percentage = if reference_ir == 0 {
-                                if ir == 0 { 0.0 } else { 100.0 }
-                            } else {
-                                ((ir as f64 - reference_ir as f64) / reference_ir as f64) * 100.0
-                            };
-                            record.push(format!("{percentage:.3}"));
+                    }
+                } else {
+                    // Selective data inclusion
+                    for (i, &ir) in irs.iter().enumerate() {
+                        record.push(self.csv_cost(i, ir));
+
+                        if compared(i) {
+                            if include_differences {
+                                record.push(diff(ir).unwrap_or_default());
+                            }
+
+                            if include_percentages {
+                                record.push(percentage(ir).unwrap_or_default());
+                            }
                         }
                     }
                 }
@@ -396,24 +1508,206 @@ impl Records {
     }
 }
 
-/// A symbol in the file and its IR count for a single run.
-#[derive(Default)]
+/// A cell of a CSV file holding a cost.
+enum CsvCost {
+    /// A known cost.
+    Count(i64),
+    /// A cost below the threshold of its run, given as a percentage of its total (`<1%`).
+    BelowThreshold(f64),
+    /// No cost, for an event that the run did not record.
+    Empty,
+}
+
+/// Read the costs of a row of a CSV file, given the `(event, run)` indices each column maps to.
+///
+/// Returns the `(event, run)` indices and the cost of each cell that is not empty, or the first
+/// cell that is not a cost.
+fn read_csv_costs<'a>(
+    record: &'a csv::StringRecord,
+    columns: &[Option<(usize, usize)>],
+) -> Result<Vec<(usize, usize, CsvCost)>, &'a str> {
+    let mut cells = Vec::new();
+    for (i, &column) in columns.iter().enumerate() {
+        let (Some((event, run)), Some(cell)) = (column, record.get(i + 1)) else {
+            continue;
+        };
+        match parse_csv_cost(cell) {
+            Some(CsvCost::Empty) => {}
+            Some(cost) => cells.push((event, run, cost)),
+            None => return Err(cell.trim()),
+        }
+    }
+    Ok(cells)
+}
+
+/// Parse a cell of a CSV file holding a cost, or return `None` if it does not hold one.
+fn parse_csv_cost(cell: &str) -> Option<CsvCost> {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        Some(CsvCost::Empty)
+    } else if let Some(below) = cell.strip_prefix('<').and_then(|below| below.strip_suffix('%')) {
+        below.parse().ok().map(CsvCost::BelowThreshold)
+    } else {
+        cell.parse().ok().map(CsvCost::Count)
+    }
+}
+
+/// Return the run of each column of the output of [`Records::to_csv_file_enhanced`], given its
+/// header: `<run>` for a `<run>_ir` column, and `None` for `_diff` and `_pct` columns.
+///
+/// Returns `None` if the columns are not those of an enhanced export.
+fn enhanced_csv_runs(column_names: &[String]) -> Option<Vec<Option<String>>> {
+    let runs = column_names
+        .iter()
+        .map(|column| match column.strip_suffix("_ir") {
+            Some(run) => Some(Some(run.to_string())),
+            None if column.ends_with("_diff") || column.ends_with("_pct") => Some(None),
+            None => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    runs.iter().any(Option::is_some).then_some(runs)
+}
+
+/// Return the values of a run among values laid out as `values[event][run]`, for the given events.
+fn run_values<'a, T: Copy>(
+    values: &'a [Vec<T>],
+    events: &'a [usize],
+    run: usize,
+) -> impl Iterator<Item = T> + 'a {
+    events.iter().map(move |&event| values[event][run])
+}
+
+/// Format costs for a message, as `<cost> <event>, ...`.
+fn format_costs(events: &[String], costs: &[i64]) -> String {
+    events.iter().zip(costs).map(|(event, cost)| format!("{cost} {event}")).join(", ")
+}
+
+/// Build the name under which a symbol is shown and matched across runs.
+///
+/// This follows the layout of `callgrind_annotate`: `<file>:<name> [<object>]`, where the file and
+/// the object only appear if they are known.
+fn symbol_key(name: &str, file: Option<&str>, object: Option<&str>) -> String {
+    let mut key = String::new();
+    if let Some(file) = file {
+        key.push_str(file);
+        key.push(':');
+    }
+    key.push_str(name);
+    if let Some(object) = object {
+        key.push_str(" [");
+        key.push_str(object);
+        key.push(']');
+    }
+    key
+}
+
+/// Return whether the symbol name is only an address (`0x0000000000001070`), as given for code
+/// without symbols.
+fn is_address(name: &str) -> bool {
+    name.strip_prefix("0x")
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// A symbol in the file and its costs for a single run.
+#[derive(Default, Clone)]
 pub struct AnnotatedSymbol {
     /// The name of the symbol.
     pub name: String,
-    /// The instruction count for that run.
-    pub ir: u64,
+    /// The source file the symbol is defined in, if known.
+    pub file: Option<String>,
+    /// The object (binary or shared library) the symbol is in, if known.
+    pub object: Option<String>,
+    /// The self cost of each event for that run, in the order of [`Run::events`].
+    pub costs: Vec<i64>,
+    /// The inclusive cost of each event for that run, in the order of [`Run::events`].
+    ///
+    /// This is the self cost plus the cost of the calls made by the symbol.
+    pub inclusive: Vec<i64>,
+    /// The statistics of the main cost of each event over the samples, if the run is a sample
+    /// group (see [`Run::from_samples`]).
+    pub stats: Option<Vec<SampleStats>>,
+}
+
+impl AnnotatedSymbol {
+    /// Return the name under which the symbol is shown and matched across runs.
+    pub fn key(&self) -> String {
+        symbol_key(&self.name, self.file.as_deref(), self.object.as_deref())
+    }
 }
 
-/// A symbol in the file and its IR counts for multiple runs.
+/// A symbol in the file and its costs for multiple runs.
 #[derive(Default)]
 pub struct RecordsSymbol {
     /// The name of the symbol.
     pub name: String,
-    /// The instruction counts for different runs.
+    /// The source file the symbol is defined in, if known.
+    pub file: Option<String>,
+    /// The object (binary or shared library) the symbol is in, if known.
+    pub object: Option<String>,
+    /// The costs for different runs, for each event (`costs[event][run]`).
+    ///
+    /// A cost is `None` if the symbol was left out of the listing of a run because of its
+    /// threshold: it is unknown, but below [`Records::runs_below_threshold`].
     ///
     /// When storing a collection of [`RecordsSymbol`]s, care must be taken in order to not assign
-    /// an IR count of one run to another (i.e. before inserting, the length of `irs` for each
-    /// [`RecordsSymbol`] in the collection must be the same).
-    pub irs: Vec<u64>,
+    /// a cost of one run to another (i.e. before inserting, the length of each entry of `costs`
+    /// for each [`RecordsSymbol`] in the collection must be the same).
+    pub costs: Vec<Vec<Option<i64>>>,
+    /// The inclusive costs for different runs, for each event (`inclusive[event][run]`).
+    ///
+    /// The same care as for [`Self::costs`] must be taken.
+    pub inclusive: Vec<Vec<Option<i64>>>,
+    /// The keys of the symbols whose rows were merged into this one because they were renamed to
+    /// it, in the order they were merged (see [`Records::merge_aliases`]).
+    pub renamed_from: Vec<String>,
+    /// The statistics of the main costs over the samples of each run that is a sample group,
+    /// for each event (`stats[event][run]`), or `None` for other runs and unknown costs.
+    ///
+    /// The same care as for [`Self::costs`] must be taken.
+    pub stats: Vec<Vec<Option<SampleStats>>>,
+}
+
+impl RecordsSymbol {
+    /// Return the name under which the symbol is matched across runs.
+    pub fn key(&self) -> String {
+        symbol_key(&self.name, self.file.as_deref(), self.object.as_deref())
+    }
+
+    /// Return the name under which the symbol is shown: its key, marked with the names it was
+    /// renamed from, if any (`new (was old)`).
+    pub fn display_name(&self) -> String {
+        let key = self.key();
+        if self.renamed_from.is_empty() {
+            key
+        } else {
+            format!("{key} (was {})", self.renamed_from.join(", "))
+        }
+    }
+}
+
+/// A line of annotated source and its costs for a single run.
+#[derive(Clone)]
+pub struct AnnotatedLine {
+    /// The annotated file.
+    pub file: String,
+    /// The number of the line in the file (1-based).
+    pub line: u32,
+    /// The source code of the line.
+    pub text: String,
+    /// The cost of each event for that run, in the order of [`Run::events`].
+    pub costs: Vec<i64>,
+}
+
+/// A line of annotated source and its costs for multiple runs.
+pub struct RecordsLine {
+    /// The annotated file.
+    pub file: String,
+    /// The number of the line in the file (1-based).
+    pub line: u32,
+    /// The source code of the line, as annotated in the first run that has it.
+    pub text: String,
+    /// The costs for different runs, for each event (`costs[event][run]`).
+    ///
+    /// The same care as for [`RecordsSymbol::costs`] must be taken.
+    pub costs: Vec<Vec<i64>>,
 }
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+use crate::runs::{Records, RecordsSymbol};
+
+/// Statistics of a cost over the samples of a sample group (see `--sample-group`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SampleStats {
+    /// The number of samples.
+    pub samples: usize,
+    /// The mean of the cost.
+    pub mean: f64,
+    /// The lowest cost.
+    pub min: i64,
+    /// The highest cost.
+    pub max: i64,
+    /// The standard deviation of the cost (corrected, over `samples - 1`), 0 for a single sample.
+    pub std_dev: f64,
+}
+
+impl SampleStats {
+    /// Compute the statistics of the given costs, one per sample.
+    ///
+    /// Returns `None` if there is no sample.
+    pub fn new(costs: &[i64]) -> Option<Self> {
+        let (&min, &max) = (costs.iter().min()?, costs.iter().max()?);
+        let n = costs.len() as f64;
+        let mean = costs.iter().map(|&cost| cost as f64).sum::<f64>() / n;
+        let squares = costs.iter().map(|&cost| (cost as f64 - mean).powi(2)).sum::<f64>();
+        let std_dev = if costs.len() > 1 {
+            (squares / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        Some(Self {
+            samples: costs.len(),
+            mean,
+            min,
+            max,
+            std_dev,
+        })
+    }
+
+    /// Return the mean, rounded to a cost.
+    pub fn rounded_mean(&self) -> i64 {
+        self.mean.round() as i64
+    }
+
+    /// Return the variance of the mean (the squared standard error).
+    fn mean_variance(&self) -> f64 {
+        self.std_dev.powi(2) / self.samples as f64
+    }
+}
+
+impl Records {
+    /// Return whether the runs that are sample groups of 2 samples or more can be compared with
+    /// each other: there are two of them at least.
+    pub fn has_sample_groups(&self) -> bool {
+        let groups = self.profile_runs().filter(|&run| self.runs_metadata[run].samples.len() > 1);
+        groups.count() > 1
+    }
+
+    /// Return whether the difference of the main cost of a symbol for an event between a run and
+    /// its reference is significant at the given level (see [`welch_p_value`]).
+    ///
+    /// Returns `None` if either run is not a sample group of 2 samples or more, or if the cost of
+    /// the symbol is unknown in either.
+    pub fn is_significant(
+        &self,
+        symbol: &RecordsSymbol,
+        event: usize,
+        run: usize,
+        reference: usize,
+        level: f64,
+    ) -> Option<bool> {
+        // Sample groups keep the statistics of their self costs, if they have any.
+        let inclusive = !self.has_self_costs();
+        if [run, reference].iter().any(|&run| self.runs_loaded[run].self_cost == inclusive) {
+            return None;
+        }
+        let stats = &symbol.stats[event];
+        let p_value = welch_p_value(stats[run].as_ref()?, stats[reference].as_ref()?)?;
+        Some(p_value < level)
+    }
+
+    /// Return whether the difference of the total cost for an event between a run and its
+    /// reference is significant at the given level, as for [`Self::is_significant`].
+    pub fn is_total_significant(
+        &self,
+        event: usize,
+        run: usize,
+        reference: usize,
+        level: f64,
+    ) -> Option<bool> {
+        let stats = &self.runs_totals_stats[event];
+        let p_value = welch_p_value(stats[run].as_ref()?, stats[reference].as_ref()?)?;
+        Some(p_value < level)
+    }
+}
+
+/// Return the two-sided p-value of Welch's t-test of the means of two sample groups: how likely
+/// a difference at least as large is to come from noise alone.
+///
+/// Returns `None` if either group has fewer than 2 samples, whose variance is unknown. When both
+/// groups have no variance at all, the difference is certain (0) unless the means are equal (1).
+pub fn welch_p_value(a: &SampleStats, b: &SampleStats) -> Option<f64> {
+    if a.samples < 2 || b.samples < 2 {
+        return None;
+    }
+    let (a_variance, b_variance) = (a.mean_variance(), b.mean_variance());
+    let variance = a_variance + b_variance;
+    if variance == 0.0 {
+        // All the samples of each group have the same cost.
+        return Some(if a.min == b.min { 1.0 } else { 0.0 });
+    }
+    let t = (a.mean - b.mean) / variance.sqrt();
+    // The Welch–Satterthwaite degrees of freedom.
+    let freedom = variance.powi(2)
+        / (a_variance.powi(2) / (a.samples - 1) as f64
+            + b_variance.powi(2) / (b.samples - 1) as f64);
+    // The tails of Student's t distribution, through the incomplete beta function.
+    Some(regularized_beta(freedom / (freedom + t * t), freedom / 2.0, 0.5))
+}
+
+/// Return the regularized incomplete beta function `I_x(a, b)`.
+///
+/// This follows the continued fraction of Numerical Recipes (6.4), which converges quickly on
+/// one side of `(a + 1) / (a + b + 2)`, and the symmetry `I_x(a, b) = 1 - I_(1-x)(b, a)` on the
+/// other.
+fn regularized_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln())
+        .exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Evaluate the continued fraction of the incomplete beta function with Lentz's method.
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: u32 = 300;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+    let not_tiny = |value: f64| if value.abs() < TINY { TINY } else { value };
+
+    // The terms of Lentz's method.
+    let mut lentz_c = 1.0;
+    let mut lentz_d = 1.0 / not_tiny(1.0 - (a + b) * x / (a + 1.0));
+    let mut fraction = lentz_d;
+    for step in 1..=MAX_ITERATIONS {
+        let step = f64::from(step);
+        // The even then odd steps of the recurrence.
+        let even = step * (b - step) * x / ((a + 2.0 * step - 1.0) * (a + 2.0 * step));
+        let odd = -(a + step) * (a + b + step) * x / ((a + 2.0 * step) * (a + 2.0 * step + 1.0));
+        for numerator in [even, odd] {
+            lentz_d = 1.0 / not_tiny(1.0 + numerator * lentz_d);
+            lentz_c = not_tiny(1.0 + numerator / lentz_c);
+            fraction *= lentz_d * lentz_c;
+        }
+        if (lentz_d * lentz_c - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    fraction
+}
+
+/// Return the natural logarithm of the gamma function, with the Lanczos approximation (g = 7).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // The reflection formula.
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, coefficient)| sum + coefficient / (x + i as f64 + 1.0));
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
@@ -1,26 +1,1071 @@
-use std::process::Command;
+use std::{
+    fs::File,
+    process::{Command, Output},
+};
+
+/// Run the binary with the given arguments.
+fn run(args: &[&str]) -> Output {
+    Command::new("cargo")
+        .args(["run", "--quiet", "--"])
+        .args(args)
+        .output()
+        .expect("Failed to execute command")
+}
 
 #[test]
 fn test_help_flag() {
     let output = Command::new("cargo")
-        .args(&["run", "--", "--help"])
+        .args(["run", "--", "--help"])
         .output()
         .expect("Failed to execute command");
 
     assert!(output.status.success());
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("callgrind_differ"));
+    assert!(stdout.contains("callgrind-compare"));
     assert!(stdout.contains("Usage:"));
 }
 
 #[test]
 fn test_version_info() {
     let output = Command::new("cargo")
-        .args(&["run", "--", "--version"])
+        .args(["run", "--", "--version"])
         .output()
         .expect("Failed to execute command");
 
     assert!(output.status.success());
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("callgrind_differ"));
+    assert!(stdout.contains("callgrind-compare"));
+}
+
+#[test]
+fn test_raw_callgrind_matches_annotate() {
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/callgrind.out.4242",
+        "--color",
+        "never",
+    ]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Every symbol has the same IR count in both files, so only the total line is shown.
+    assert_eq!(stdout.lines().count(), 4, "{stdout}");
+    assert!(stdout.contains("Total IR | 5000 |     -        -  5000"));
+}
+
+#[test]
+fn test_event_selection() {
+    let output = run(&[
+        "test_data/tiny/tiny_events.cg",
+        "test_data/tiny/callgrind.out.4343",
+        "--event",
+        "Dr",
+        "--color",
+        "never",
+    ]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 4, "{stdout}");
+    assert!(stdout.contains("Total  | 1200 |     -        -  1200"), "{stdout}");
+
+    let output = run(&["test_data/tiny/tiny_events.cg", "--event", "D1mr"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unknown event D1mr"), "{stderr}");
+}
+
+#[test]
+fn test_csv_export_several_events() {
+    let path = std::env::temp_dir().join("callgrind-compare-several-events.csv");
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/callgrind.out.4343",
+        "--csv-names",
+        "before",
+        "--csv-names",
+        "after",
+        "--event",
+        "Ir,Dr",
+        "--csv-export",
+        path.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    let csv = std::fs::read_to_string(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("name,before:Ir,after:Ir,before:Dr,after:Dr")
+    );
+    // The first run did not record `Dr`: its costs are unknown rather than 0.
+    assert!(csv.contains("\nPROGRAM TOTALS,5000,4600,,1200\n"), "{csv}");
+    assert!(csv.contains("\nwork,3000,2600,,800\n"), "{csv}");
+
+    // They are still unknown once the export is loaded back.
+    let output = run(&[path.to_str().unwrap(), "--event", "Dr", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\nwork         |      |     ?        ?   800\n"), "{stdout}");
+}
+
+#[test]
+fn test_group_by() {
+    let output = run(&["test_data/tiny/static.cg", "-a", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("helper   | 1700"), "{stdout}");
+
+    let output = run(&[
+        "test_data/tiny/static.cg",
+        "-a",
+        "--color",
+        "never",
+        "--group-by",
+        "file:function",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("a.c:helper  | 1200"), "{stdout}");
+    assert!(stdout.contains("b.c:helper  |  500"), "{stdout}");
+
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "-a",
+        "--color",
+        "never",
+        "--group-by",
+        "object",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("/usr/lib/x86_64-linux-gnu/libc.so.6 | 1900"),
+        "{stdout}"
+    );
+
+    // JSON runs are grouped as well, as long as they keep what is grouped by.
+    let path = std::env::temp_dir().join("callgrind-compare-group-by.json");
+    let path = path.to_str().unwrap();
+    let args = ["test_data/tiny/static.cg", "--group-by", "file:function", "--json-export", path];
+    assert!(run(&args).status.success());
+    let output = run(&[path, "test_data/tiny/static.cg", "-a", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("helper   | 1700 |"), "{stdout}");
+    let output = run(&[path, "--group-by", "object"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("does not keep the objects of its symbols"), "{stderr}");
+}
+
+#[test]
+fn test_parse_errors() {
+    let output = run(&["test_data/tiny/malformed.cg"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("test_data/tiny/malformed.cg:27: invalid count \"1,5x0\""),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+
+    let output = run(&["test_data/tiny/truncated.cg"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("test_data/tiny/truncated.cg:20: expected a separation line, found end of file"),
+        "{stderr}"
+    );
+
+    let path = std::env::temp_dir().join("callgrind-compare-empty.cg");
+    std::fs::write(&path, "").unwrap();
+    let output = run(&[path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("expected an `Events shown:` header line, found end of file"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_lenient() {
+    let output = run(&["test_data/tiny/malformed.cg", "--lenient", "-a", "--color", "never"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: test_data/tiny/malformed.cg:27: invalid count \"1,5x0\" (skipped)"),
+        "{stderr}"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("work     | 3000"), "{stdout}");
+    assert!(!stdout.contains("(below main)"), "{stdout}");
+}
+
+#[test]
+fn test_inclusive_costs() {
+    // The self and inclusive outputs of the same profile are loaded as a single run.
+    let output = run(&[
+        "test_data/tiny/callgrind.out.4242",
+        "--merge-into",
+        "tiny=test_data/tiny/tiny.cg,test_data/tiny/tiny_inclusive.cg",
+        "--show",
+        "ircount,inclircount",
+        "-a",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 8, "{stdout}");
+    assert!(stdout.contains("main         |  100 3100 |  100 3100"), "{stdout}");
+
+    // Given one after the other, they are different runs.
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/tiny_inclusive.cg",
+        "--show",
+        "ircount",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("holds no self costs"), "{stderr}");
+
+    // The header tells that a listing is inclusive, although its costs add up to less than the
+    // totals.
+    let output = run(&[
+        "test_data/tiny/tiny_inclusive_90.cg",
+        "--show",
+        "inclircount",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Only the options of `callgrind_annotate` tell, not the arguments of the profiled program.
+    let output = run(&["test_data/tiny/tiny_target_option.cg", "--show", "inclircount"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("holds no inclusive costs"), "{stderr}");
+
+    // Inclusive columns are shown when no self costs were loaded.
+    let output = run(&[
+        "test_data/tiny/tiny_inclusive.cg",
+        "test_data/tiny/callgrind.out.4242",
+        "-a",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("(below main) | 4600 |     -        -  4600"), "{stdout}");
+
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/callgrind.out.4242",
+        "--show",
+        "inclircountdiff",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Run tiny holds no inclusive costs"), "{stderr}");
+}
+
+#[test]
+fn test_source_view() {
+    let output = run(&[
+        "test_data/tiny/tiny_src_before.cg",
+        "test_data/tiny/tiny_src_after.cg",
+        "--csv-names",
+        "before",
+        "--csv-names",
+        "after",
+        "--source",
+        "tiny.c",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("Line | befo | after | Source"));
+    assert_eq!(lines.next(), Some("-----+------+-------+- tiny.c"));
+    assert!(
+        stdout.contains("   7 | 2000 | - 500 |     for (int i = 0; i < n * 100; i++)\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("   8 |  990 | - 100 |         sum += i;\n"), "{stdout}");
+    // The call to `work` is not a line of source.
+    assert!(!stdout.contains("=>"), "{stdout}");
+    assert_eq!(stdout.lines().count(), 15, "{stdout}");
+
+    let output = run(&["test_data/tiny/tiny_src_before.cg", "--source", "exit.c"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("No annotated source for exit.c (annotated files: tiny.c)"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cachegrind_inputs() {
+    // `callgrind_annotate`, `cg_annotate` and raw cachegrind outputs line up by symbol.
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/tiny_cg.cg",
+        "test_data/tiny/cachegrind.out.4545",
+        "-a",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 8, "{stdout}");
+    assert!(
+        stdout.contains("work         | 3000 | - 200 -  6.667% 2800 | - 200 -  6.667% 2800"),
+        "{stdout}"
+    );
+
+    // Cache misses are loaded from both.
+    let output = run(&[
+        "test_data/tiny/tiny_cg.cg",
+        "test_data/tiny/cachegrind.out.4545",
+        "-a",
+        "--event",
+        "I1mr",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Total        | 14 |   -        -  14"), "{stdout}");
+    assert!(stdout.contains("main         |  4 |   -        -   4"), "{stdout}");
+
+    let output = run(&["test_data/tiny/tiny_cg.cg", "--source", "tiny.c", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("  22 | 1800 |     for (int i = 0; i < n * 100; i++) sum += i;\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_delta_runs() {
+    // Differences, raw from `cg_diff` or annotated, are shown as is and skipped as references.
+    let output = run(&[
+        "test_data/tiny/tiny_diff.cg",
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/cgdiff.out",
+        "test_data/tiny/callgrind.out.4343",
+        "--relative-to",
+        "previous",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Total IR | - 580 | 5000 | - 580 | - 400 -  8.000% 4600"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("work     | - 600 | 3000 | - 600 | - 400 - 13.333% 2600"),
+        "{stdout}"
+    );
+    // `main` did not change between the profiles, but does in the differences.
+    assert!(stdout.contains("main     | +  20 |  100 | +  20 |     -        -   100"), "{stdout}");
+
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/cgdiff.out",
+        "--relative-to",
+        "column1",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is a delta run"), "{stderr}");
+}
+
+#[test]
+fn test_stdin_and_compressed_inputs() {
+    // Gzip, zstd and xz inputs are detected from their content once decompressed.
+    let output = Command::new("cargo")
+        .args(["run", "--quiet", "--"])
+        .args([
+            "test_data/tiny/tiny.cg.gz",
+            "-",
+            "test_data/tiny/cachegrind.out.4545.xz",
+            "test_data/tiny/callgrind.out.4343.zst",
+            "--color",
+            "never",
+        ])
+        .stdin(File::open("test_data/tiny/tiny_cg.cg").expect("Failed to open the fixture"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | tiny |        stdin         | "), "{stdout}");
+    assert!(
+        stdout.contains(
+            "work     | 3000 | - 200 -  6.667% 2800 | - 200 -  6.667% 2800 | - 400 - 13.333% 2600"
+        ),
+        "{stdout}"
+    );
+
+    let output = run(&["-", "-"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_input_specs() {
+    // `irq.txt` is a CSV file that looks like a `callgrind_annotate` one.
+    let output = run(&["test_data/tiny/tiny.cg", "test_data/tiny/irq.txt"]);
+    assert!(!output.status.success());
+
+    // `--csv-names` names the inputs without a name of their own.
+    let output = run(&[
+        "baseline=annotate:test_data/tiny/tiny.cg",
+        "raw:test_data/tiny/callgrind.out.4343",
+        "csv:test_data/tiny/irq.txt",
+        "--csv-names",
+        "after",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("Symbol       | base |        after         |        Run 1        \n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Irq_handler  |    0 |     -        -     0 | + 120 +100.000%  120"));
+
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "after=test_data/tiny/callgrind.out.4343",
+        "--csv-names",
+        "before",
+        "--csv-names",
+        "after",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("`csv-names` count 2"), "{stderr}");
+}
+
+#[test]
+fn test_directory_and_glob_inputs() {
+    // Files are named after what differs in their names, `run10` after `run2`.
+    let output = run(&["test_data/series", "--show", "ircount", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | run1 | run2 | run1\n"), "{stdout}");
+    assert!(stdout.contains("work     | 3000 | 2900 | 2800\n"), "{stdout}");
+
+    // The PIDs are those of the `Profiled target:` lines.
+    let output = run(&[
+        "test_data/series/*.cg",
+        "--input-order",
+        "pid",
+        "--show",
+        "ircount",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("work     | 2800 | 3000 | 2900\n"), "{stdout}");
+
+    let output = run(&[
+        "test_data/tiny/callgrind.out.4[0-9][0-9][0-9]",
+        "--show",
+        "ircount",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | 4242 | 4343\n"), "{stdout}");
+
+    // By a field of the header, `desc: Timerange: Basic block 0 - 1100` first.
+    let output = run(&[
+        "test_data/tiny/callgrind.out.4[0-9][0-9][0-9]",
+        "--input-order",
+        "field:Timerange",
+        "--show",
+        "ircount",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | 4343 | 4242\n"), "{stdout}");
+
+    // Names that differ within a multi-byte character.
+    let dir = std::env::temp_dir().join("callgrind-compare-accents");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy("test_data/tiny/tiny.cg", dir.join("a\u{e9}.cg")).unwrap();
+    std::fs::copy("test_data/tiny/tiny_renamed.cg", dir.join("a\u{e8}.cg")).unwrap();
+    let output = run(&[dir.to_str().unwrap(), "--show", "ircount", "--color", "never"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | a\u{e8}"), "{stdout}");
+    assert!(stdout.contains("| a\u{e9}"), "{stdout}");
+
+    let output = run(&["test_data/series/*.missing"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_metadata() {
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/callgrind.out.4343",
+        "--show-metadata",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(
+            "Run tiny\n  Data file:        callgrind.out.4242\n  Creator:          callgrind-3.18.1\n  \
+             Command:          ./test_program_tiny 10\n  PID:              4242\n"
+        ),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Run callgrind.out\n"), "{stdout}");
+    assert!(stdout.contains("  Events recorded:  Ir Dr Dw\n"), "{stdout}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: Runs have different recorded events: `Ir` (tiny), `Ir Dr Dw`"),
+        "{stderr}"
+    );
+
+    let output = run(&["test_data/tiny/tiny.cg", "test_data/tiny/callgrind.out.4343", "--strict"]);
+    assert!(!output.status.success());
+
+    // The PIDs differ, but not the target.
+    let output = run(&["test_data/series", "--strict"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_below_threshold() {
+    let path = std::env::temp_dir().join("callgrind-compare-below-threshold.csv");
+    let output = run(&[
+        "test_data/real_callgrind/simple_small_high_threshold.cg",
+        "test_data/real_callgrind/simple_small_low_threshold.cg",
+        "--sort-by=-last-ir",
+        "--color",
+        "never",
+        "--csv-export",
+        path.to_str().unwrap(),
+        "--csv-all-data",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Left out by `--threshold=90`, so below 10% rather than 0.
+    assert!(stdout.contains("|   14516 |        ?        ?     <10%\n"), "{stdout}");
+    // Unknown costs come last, whatever the order.
+    assert!(stdout.trim_end().ends_with("<10%"), "{stdout}");
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert!(csv.contains("\n_int_malloc,14516,<10%,,\n"), "{csv}");
+
+    // The CSV export keeps unknown costs as such.
+    let output = run(&[
+        "test_data/real_callgrind/simple_small_high_threshold.cg",
+        "test_data/real_callgrind/simple_small_low_threshold.cg",
+        "--csv-export",
+        path.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let output = run(&[path.to_str().unwrap(), "--sort-by=first-ir", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("|   14516 |        ?        ?     <10%\n"), "{stdout}");
+}
+
+#[test]
+fn test_merge_into() {
+    // The symbols left out by the threshold of `tiny_90.cg` are taken from `tiny.cg`.
+    let output = run(&[
+        "test_data/tiny/tiny_90.cg",
+        "--merge-into",
+        "full=test_data/tiny/tiny_90.cg,test_data/tiny/tiny.cg",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | tiny |         full        \n"), "{stdout}");
+    assert!(stdout.contains("exit     | <10% |     ?        ?   400\n"), "{stdout}");
+
+    let merge = "full=test_data/tiny/tiny.cg,test_data/tiny/tiny_50_conflict.cg";
+    let output = run(&["test_data/tiny/tiny.cg", "--merge-into", merge]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "warning: full: `tiny.c:work [/tmp/tiny/test_program_tiny]` costs 3000 Ir in \
+             test_data/tiny/tiny.cg but 3100 Ir in test_data/tiny/tiny_50_conflict.cg"
+        ),
+        "{stderr}"
+    );
+
+    let output = run(&["--merge-into", merge, "--strict"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_parts_and_threads() {
+    // The threads of the same process are labeled, or summed.
+    let output = run(&["test_data/threads", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | 5151 |    5151-02 p1 t2    \n"), "{stdout}");
+    assert!(stdout.contains("work     | 1500 | +1000 + 66.667% 2500\n"), "{stdout}");
+
+    let output = run(&[
+        "test_data/tiny/callgrind.out.4242",
+        "test_data/threads",
+        "--parts",
+        "sum",
+        "--show-metadata",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("  Parts:            1\n  Threads:          1 2\n"), "{stdout}");
+    assert!(stdout.contains("work         | 3000 | +1000 + 33.333% 4000\n"), "{stdout}");
+
+    // Outputs of the same part at different thresholds are not added up.
+    let output = run(&["test_data/tiny/tiny.cg", "test_data/tiny/tiny_90.cg", "--parts", "sum"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_parts_by_command() {
+    // `--trace-children=yes` gives a profile per process, added up by command line.
+    let output = run(&[
+        "test_data/tiny/callgrind.out.4242",
+        "test_data/children",
+        "--parts",
+        "command",
+        "--parts-breakdown",
+        "--color",
+        "never",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(
+            "Symbol       | call |         6161         | pid 6161 | pid 6262 |         6363        \n"
+        ),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(
+            "work         | 3000 | +1000 + 33.333% 4000 |     1500 |     2500 | -3000 -100.000%    0\n"
+        ),
+        "{stdout}"
+    );
+
+    // Breakdown columns are never the reference.
+    let output = run(&[
+        "test_data/children",
+        "--parts",
+        "command",
+        "--parts-breakdown",
+        "--relative-to",
+        "column1",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_enhanced_csv_round_trip() {
+    let path = std::env::temp_dir().join("callgrind-compare-enhanced.csv");
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/callgrind.out.4343",
+        "--csv-export",
+        path.to_str().unwrap(),
+        "--csv-all-data",
+    ]);
+    assert!(output.status.success());
+
+    // Only the `_ir` columns are loaded back, as runs named after them.
+    let output = run(&[path.to_str().unwrap(), "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Symbol   | tiny |    callgrind.out    \n"), "{stdout}");
+    assert!(stdout.contains("work     | 3000 | - 400 - 13.333% 2600\n"), "{stdout}");
+
+    // Cells that are not counts are reported rather than taken as 0.
+    std::fs::write(&path, "name,before,after\nwork,3000,n/a\nmain,100,120\n").unwrap();
+    let output = run(&[path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(":2: invalid count \"n/a\""), "{stderr}");
+
+    let output = run(&[path.to_str().unwrap(), "--lenient", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("work"), "{stdout}");
+    assert!(stdout.contains("main     | 100 | + 20 + 20.000% 120\n"), "{stdout}");
+}
+
+#[test]
+fn test_csv_round_trip_totals() {
+    let inputs = [
+        "test_data/real_callgrind/simple_small_high_threshold.cg",
+        "test_data/real_callgrind/simple_small_low_threshold.cg",
+    ];
+    let total_line = |output: &std::process::Output| {
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        stdout.lines().find(|line| line.starts_with("Total IR")).unwrap().to_string()
+    };
+    let expected = total_line(&run(&[inputs[0], inputs[1], "--color", "never"]));
+
+    // The listed symbols do not add up to the totals, which are exported as a row of their own.
+    for (name, options) in [("plain", &[][..]), ("enhanced", &["--csv-all-data"][..])] {
+        let path = std::env::temp_dir().join(format!("callgrind-compare-totals-{name}.csv"));
+        let mut args = vec![inputs[0], inputs[1], "--csv-export", path.to_str().unwrap()];
+        args.extend_from_slice(options);
+        assert!(run(&args).status.success());
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        assert!(csv.lines().nth(1).unwrap().starts_with("PROGRAM TOTALS,"), "{csv}");
+        let output = run(&[path.to_str().unwrap(), "--color", "never"]);
+        assert_eq!(total_line(&output), expected);
+    }
+}
+
+#[test]
+fn test_json_round_trip() {
+    let inputs = [
+        "test_data/tiny/tiny_90.cg",
+        "test_data/tiny/callgrind.out.4343",
+        "--show-metadata",
+        "--color",
+        "never",
+    ];
+    let expected = run(&inputs);
+    assert!(expected.status.success());
+
+    // Runs, metadata, thresholds and unknown costs are the same once loaded back.
+    for format in ["json", "ndjson"] {
+        let path = std::env::temp_dir().join(format!("callgrind-compare-round-trip.{format}"));
+        let mut args = inputs.to_vec();
+        args.extend(["--json-export", path.to_str().unwrap(), "--json-format", format]);
+        assert!(run(&args).status.success());
+
+        let output = run(&[path.to_str().unwrap(), "--show-metadata", "--color", "never"]);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&expected.stdout)
+        );
+    }
+
+    // Each line of an NDJSON file is a symbol, which `--lenient` skips if malformed.
+    let path = std::env::temp_dir().join("callgrind-compare-round-trip.ndjson");
+    let ndjson = std::fs::read_to_string(&path).unwrap();
+    let lines = ndjson.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with(r#"{"format":"callgrind-compare","version":1,"#));
+    let work = lines.iter().position(|line| line.contains(r#""name":"work""#)).unwrap();
+    let mut malformed = lines.clone();
+    malformed[work] = r#"{"name":"work","costs":[[1]]}"#;
+    std::fs::write(&path, malformed.join("\n")).unwrap();
+
+    let output = run(&[path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let error = format!(":{}: invalid JSON: expected costs for 2 runs", work + 1);
+    assert!(stderr.contains(&error), "{stderr}");
+
+    let output = run(&[path.to_str().unwrap(), "--lenient", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("work"), "{stdout}");
+}
+
+#[test]
+fn test_normalize() {
+    // The same functions, built with legacy and v0 Rust mangling.
+    let output = run(&["test_data/mangled", "--color", "never"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("_ZN4demo4work17h2c8c0255776a0dbcE"), "{stdout}");
+
+    let output = run(&["test_data/mangled", "--color", "never", "--normalize", "demangle"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("demo::work::h2c8c0255776a0dbc "), "{stdout}");
+    assert!(stdout.contains("demo[1ba8404ce28d149d]::work::<u8>::{closure#0} "), "{stdout}");
+    assert!(!stdout.contains("std::vector"), "{stdout}");
+
+    let output = run(&[
+        "test_data/mangled",
+        "--color",
+        "never",
+        "-a",
+        "--normalize",
+        "generics,closures,hashes,demangle",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().skip(4).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "demo::main                            |  100 |     -        -   100",
+            "demo::work                            | 1000 | - 100 - 10.000%  900",
+            "demo::work::{closure}                 |  500 |     -        -   500",
+            "std::vector<_>::push_back(int const&) |  300 |     -        -   300",
+        ]
+    );
+}
+
+#[test]
+fn test_regex_replace() {
+    // Capture groups, anchoring, and rules applied in order, each on the result of the previous.
+    let output = run(&[
+        "test_data/tiny/tiny.cg",
+        "test_data/tiny/callgrind.out.4343",
+        "--color",
+        "never",
+        "-a",
+        "--regex-replace",
+        r"^\((\w+) main\)$=>$1::main",
+        "--regex-replace",
+        "^below::=>start::",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\nstart::main | 1500 |"), "{stdout}");
+    assert!(stdout.contains("\nmain        |  100 |"), "{stdout}");
+
+    // Rules from a file come first, and apply to CSV rows as well, which may hold `/`.
+    let rules = std::env::temp_dir().join("callgrind-compare-rules.txt");
+    std::fs::write(&rules, "# Strip the source file\n^src/(\\w+)\\.rs::=>$1::\n").unwrap();
+    let csv = std::env::temp_dir().join("callgrind-compare-rules.csv");
+    std::fs::write(&csv, "name,before,after\nsrc/parser.rs::parse,300,200\n").unwrap();
+    let output = run(&[
+        csv.to_str().unwrap(),
+        "--color",
+        "never",
+        "--regex-replace-file",
+        rules.to_str().unwrap(),
+        "--regex-replace",
+        "^parser::=>crate::parser::",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\ncrate::parser::parse | 300 |"), "{stdout}");
+
+    std::fs::write(&rules, "src/(\\w+=>$1\n").unwrap();
+    let output = run(&[csv.to_str().unwrap(), "--regex-replace-file", rules.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("callgrind-compare-rules.txt:1: invalid regex"), "{stderr}");
+}
+
+#[test]
+fn test_renames() {
+    // `work` was renamed to `do_work`: it is proposed, since the names and costs are close.
+    let inputs = ["test_data/tiny/tiny.cg", "test_data/tiny/tiny_renamed.cg", "--color", "never"];
+    let mut args = inputs.to_vec();
+    args.push("--detect-renames");
+    let output = run(&args);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(
+            "Possible renames (confirm them in a file given to --aliases):\nwork=>do_work\n\n"
+        ),
+        "{stdout}"
+    );
+    assert!(stdout.contains("\nwork     | 3000 | -3000 -100.000%    0\n"), "{stdout}");
+
+    // Once confirmed, both rows are merged into the new one.
+    let aliases = std::env::temp_dir().join("callgrind-compare-aliases.txt");
+    std::fs::write(&aliases, "# Renamed in the second run\nwork => do_work\n").unwrap();
+    args.extend(["--aliases", aliases.to_str().unwrap()]);
+    let output = run(&args);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("No possible renames\n"), "{stdout}");
+    assert!(
+        stdout.contains("\ndo_work (was work) | 3000 | - 200 -  6.667% 2800\n"),
+        "{stdout}"
+    );
+
+    // Unrelated methods sharing only their last segment are not proposed, costs close or not.
+    let output = run(&[
+        "test_data/tiny/tiny_new.cg",
+        "test_data/tiny/tiny_new_renamed.cg",
+        "--detect-renames",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("No possible renames\n"), "{stdout}");
+}
+
+#[test]
+fn test_group_unresolved() {
+    let inputs = [
+        "test_data/real_callgrind/simple_small_high_threshold.cg",
+        "test_data/real_callgrind/simple_large_high_threshold.cg",
+        "--color",
+        "never",
+    ];
+    let output = run(&inputs);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\n0x"), "{stdout}");
+
+    // The addresses are added up per object, named after its file name.
+    let mut args = inputs.to_vec();
+    args.push("--group-unresolved");
+    let output = run(&args);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("\n0x"), "{stdout}");
+    assert!(
+        stdout.contains("\n<unresolved in vgpreload_core-amd64-linux.so> |       48 |"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("\n<unresolved in ???>"), "{stdout}");
+
+    // CSV inputs have no objects.
+    let output = run(&[
+        "test_data/real_callgrind/demo_output.csv",
+        "--color",
+        "never",
+        "--group-unresolved",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("\n0x"), "{stdout}");
+    assert!(stdout.contains("\n<unresolved in ???>"), "{stdout}");
+}
+
+#[test]
+fn test_sample_groups() {
+    let output = run(&[
+        "--sample-group",
+        "v1=test_data/samples/v1_1.cg,test_data/samples/v1_2.cg,test_data/samples/v1_3.cg",
+        "--sample-group",
+        "v2=test_data/samples/v2_*.cg",
+        "--color",
+        "never",
+        "--show-metadata",
+        "-a",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("  Mean totals:      Ir 5000 (min 4970, max 5030, std dev 30.0)\n"),
+        "{stdout}"
+    );
+
+    // Runs show the mean costs. `work` dropped by far more than its noise, `exit` did not.
+    assert!(stdout.contains("\nTotal IR     | 5000 | - 497 -  9.940% 4503 *\n"), "{stdout}");
+    assert!(stdout.contains("\nexit         |  400 | +   3 +  0.750%  403 ~\n"), "{stdout}");
+    assert!(stdout.contains("\nwork         | 3000 | - 500 - 16.667% 2500 *\n"), "{stdout}");
+
+    // The statistics are exported to JSON.
+    let json = std::env::temp_dir().join("callgrind-compare-samples.json");
+    let output = run(&[
+        "--sample-group",
+        "v1=test_data/samples/v1_*.cg",
+        "--sample-group",
+        "v2=test_data/samples/v2_*.cg",
+        "--json-export",
+        json.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let content = std::fs::read_to_string(&json).unwrap();
+    assert!(content.contains("\"std_dev\": 30.0"), "{content}");
+}
+
+#[test]
+fn test_check() {
+    // From `tiny_renamed.cg` to `tiny.cg`, the total goes from 4800 to 5000, and `work` appears.
+    let inputs = ["test_data/tiny/tiny_renamed.cg", "test_data/tiny/tiny.cg", "--color", "never"];
+    let mut args = inputs.to_vec();
+    args.extend(["--check", "--max-total-increase", "5%", "--max-symbol-increase", "3000"]);
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("\nCheck passed\n"));
+
+    // A pattern budget replaces `--max-symbol-increase` for the symbols it matches.
+    let mut args = inputs.to_vec();
+    args.extend(["--check", "--max-symbol-increase", "100", "--symbol-budget", "^work$=>3000"]);
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(0));
+
+    // Each cost beyond its budget is listed.
+    let mut args = inputs.to_vec();
+    args.extend(["--check", "--max-total-increase", "2%", "--symbol-budget", "^work$=>100"]);
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with(
+            "\nCheck failed, 2 over budget:\n  tiny: Total Ir +200 (+4.167%), budget 2%\n  \
+             tiny: work +3000, budget 100\n"
+        ),
+        "{stdout}"
+    );
+
+    // The other way around, the total decreases.
+    let mut args = vec!["test_data/tiny/tiny.cg", "test_data/tiny/tiny_renamed.cg"];
+    args.extend(["--check", "--max-total-increase", "0", "--improvement-threshold", "3%"]);
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(4));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with("  tiny_renamed: Total Ir -200 (-4.000%), threshold 3%\n"),
+        "{stdout}"
+    );
+    // An improvement threshold alone is enough to check.
+    let mut args = vec!["test_data/tiny/tiny.cg", "test_data/tiny/tiny_renamed.cg"];
+    args.extend(["--check", "--improvement-threshold", "3%"]);
+    assert_eq!(run(&args).status.code(), Some(4));
+
+    // Inputs that cannot be loaded.
+    let output = run(&["test_data/tiny/missing.cg", "--check", "--max-total-increase", "0"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    // Invalid arguments, whether rejected on parsing or on validation.
+    let output = run(&["test_data/tiny/tiny.cg", "--check", "--max-total-increase", "x"]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = run(&["test_data/tiny/tiny.cg", "--check"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_check_new_symbol() {
+    // `newhot` is left out of `tiny_99.cg`, which lists symbols down to 1% of its 5000 total.
+    let mut args = vec!["test_data/tiny/tiny_99.cg", "test_data/tiny/tiny_99_newhot.cg"];
+    args.extend(["--color", "never", "--check", "--max-symbol-increase", "10"]);
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with(
+            "\nCheck failed, 1 over budget:\n  \
+             tiny_99_newhot: newhot (was <1%) +3950 (+7900.000%), budget 10\n"
+        ),
+        "{stdout}"
+    );
 }